@@ -0,0 +1,56 @@
+use intervalmap::IntervalMap;
+use std::hint::black_box;
+use std::time::Instant;
+
+// Runs f a number of times and prints the average time per run
+fn bench<F: FnMut()>(name: &str, iterations: u32, mut f: F) {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    println!("{:<24} {:>12.2?}/iter", name, start.elapsed() / iterations);
+}
+
+fn main() {
+    let values: Vec<i32> = (0..100).collect();
+    let mut imap: IntervalMap<i32, i32> = IntervalMap::new(&values[0]);
+    for i in 0..1000 {
+        imap.insert(i * 10, i * 10 + 5, &values[(i % 99 + 1) as usize]);
+    }
+    let keys: Vec<i32> = (0..10_000).collect();
+
+    bench("get per key", 10, || {
+        for key in &keys {
+            black_box(imap.get(key));
+        }
+    });
+    bench("get_sorted_slice", 10, || {
+        black_box(imap.get_sorted_slice(&keys));
+    });
+
+    let frozen = imap.freeze();
+    bench("frozen get per key", 10, || {
+        for key in &keys {
+            black_box(frozen.get(key));
+        }
+    });
+
+    // A map of four segments, kept in a Vec by new_small and in a BTreeMap by the hint
+    let mut small = IntervalMap::new_small(&values[0]);
+    let mut tree = IntervalMap::with_default_and_capacity_hint(&values[0], 1000);
+    for imap in [&mut small, &mut tree] {
+        imap.insert(1000, 4000, &values[1]);
+        imap.insert(4000, 7000, &values[2]);
+        imap.insert(7000, 9000, &values[3]);
+    }
+    bench("small get per key", 10, || {
+        for key in &keys {
+            black_box(small.get(key));
+        }
+    });
+    bench("btree get per key (4)", 10, || {
+        for key in &keys {
+            black_box(tree.get(key));
+        }
+    });
+}
@@ -1,5 +1,10 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
 use std::hash::Hash;
+use std::iter::{FromIterator, Sum};
+use std::ops::Bound::{Excluded, Included, Unbounded};
+use std::ops::{Add, Mul, RangeBounds, Rem, Sub};
 
 pub trait Min {
     fn minimum() -> Self;
@@ -7,14 +12,107 @@ pub trait Min {
 
 impl Min for i32 {
     fn minimum() -> i32 {
-        std::i32::MIN
+        i32::MIN
     }
 }
 
+pub trait Max {
+    fn maximum() -> Self;
+}
+
+impl Max for i32 {
+    fn maximum() -> i32 {
+        i32::MAX
+    }
+}
+
+// Multiplication that reports overflow instead of panicking or wrapping
+pub trait CheckedMul: Sized {
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+}
+
+impl CheckedMul for i32 {
+    fn checked_mul(self, rhs: i32) -> Option<i32> {
+        i32::checked_mul(self, rhs)
+    }
+}
+
+// Subtraction that reports overflow instead of panicking or wrapping
+pub trait CheckedSub: Sized {
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+}
+
+impl CheckedSub for i32 {
+    fn checked_sub(self, rhs: i32) -> Option<i32> {
+        i32::checked_sub(self, rhs)
+    }
+}
+
+// The step between two neighbouring integer keys
+pub trait One {
+    fn one() -> Self;
+}
+
+impl One for i32 {
+    fn one() -> i32 {
+        1
+    }
+}
+
+// Errors reported by the checked constructors
+#[derive(Debug, PartialEq)]
+pub enum IntervalError {
+    Overlap, // Two ranges that should be disjoint share some keys
+}
+
+impl fmt::Display for IntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntervalError::Overlap => write!(f, "ranges overlap"),
+        }
+    }
+}
+
+impl Error for IntervalError {}
+
+// Returns the index pairs of all input ranges that overlap, without building a map. Every
+// pair is ordered (lower index first) and the pairs are sorted. Empty ranges overlap nothing
+pub fn find_overlaps<K: Ord + Copy, V>(ranges: &[(K, K, V)]) -> Vec<(usize, usize)> {
+    let mut order: Vec<usize> = (0..ranges.len())
+        .filter(|idx| ranges[*idx].0 < ranges[*idx].1)
+        .collect();
+    order.sort_by_key(|idx| ranges[*idx].0);
+
+    let mut overlaps = vec![];
+    for (pos, first) in order.iter().enumerate() {
+        let first_end = ranges[*first].1;
+        for second in order[pos + 1..]
+            .iter()
+            .take_while(|second| ranges[**second].0 < first_end)
+        {
+            overlaps.push((
+                std::cmp::min(*first, *second),
+                std::cmp::max(*first, *second),
+            ));
+        }
+    }
+    overlaps.sort();
+    overlaps
+}
+
+// Every lookup relies on the anchor segment starting at K::minimum()
+const ANCHOR_MISSING: &str = "IntervalMap invariant: anchor at K::minimum must exist";
+
+// A single segment of the map as (start, end, value). Only the last segment has no end
+pub type Segment<'a, K, V> = (K, Option<K>, &'a V);
+
+// An insert that overwrote a differing non-default value, as (start, end, old, new)
+pub type Conflict<'a, K, V> = (K, K, &'a V, &'a V);
+
 /*
 Implements a mapping that encapsulates an interval meaning that we can insert a
 start and end bound, and all values inbetween these bounds will be mapped to a value.
-The keys therefore need to be Ord and Eq, whereas the values only have to be Eq.
+The keys therefore need to be Ord and Eq, whereas the values only have to be PartialEq.
 
 Upon construction a initial value will be provided. This value will be the underlaying
 `default` interval going from -Inf to Inf. All values that do not fall into another
@@ -30,32 +128,152 @@ imap.get(&15) == 'v'
 The map is canonical, meaning that successive overlapping intervals cant have the same value:
 Non-canonical: (10, 20, 'a') (15, 25, 'a')
 Canonocal: (10, 25, 'a')
+
+The map only holds keys and references to values, so it is Send and Sync whenever K is
+Send + Sync and V is Sync. No extra bounds are needed to share it between threads, e.g.
+behind an Arc. Spawned threads need 'static data, so the values have to be borrowed for
+'static there (or use std::thread::scope).
 */
 #[derive(Debug)]
 pub struct IntervalMap<'a, K, V> {
-    m_map: BTreeMap<K, &'a V>, // Keeping track of the mapping. Keys in order
+    m_map: SegmentStore<K, &'a V>, // Keeping track of the mapping. Keys in order
+    default: &'a V,                // The initial value, remembered for resetting regions
+    bounds: Option<(K, K)>,        // The finite domain [lo, hi) for maps built with with_bounds
+    conflicts: Option<Vec<Conflict<'a, K, V>>>, // Overwrites recorded by maps built with new_logging
+}
+
+// The state of the map at a key: its value, where that value started and where it will
+// change next (None if it never does)
+#[derive(Debug, PartialEq)]
+pub struct Context<'a, K, V> {
+    pub value: &'a V,
+    pub start: K,
+    pub end: Option<K>,
 }
 
 impl<'a, K, V> IntervalMap<'a, K, V>
 where
     K: Eq + Hash + Copy + Ord + Min,
-    V: Eq,
+    V: PartialEq,
 {
     pub fn new(init_val: &'a V) -> Self {
-        let mut m_map: BTreeMap<K, &V> = BTreeMap::new();
+        IntervalMap::with_store(SegmentStore::new(), init_val)
+    }
+
+    // Same as new, but the boundaries always stay in a sorted Vec, never in a BTreeMap. This
+    // is the faster choice for maps known to stay at a handful of segments
+    pub fn new_small(init_val: &'a V) -> Self {
+        IntervalMap::with_store(SegmentStore::pinned(), init_val)
+    }
+
+    // Same as new, but picks the storage for the expected number of boundaries up front
+    // instead of switching over once the map grows
+    pub fn with_default_and_capacity_hint(init_val: &'a V, hint: usize) -> Self {
+        IntervalMap::with_store(SegmentStore::with_capacity_hint(hint), init_val)
+    }
+
+    fn with_store(mut m_map: SegmentStore<K, &'a V>, init_val: &'a V) -> Self {
         m_map.insert(K::minimum(), init_val);
-        return IntervalMap { m_map };
+        IntervalMap {
+            m_map,
+            default: init_val,
+            bounds: None,
+            conflicts: None,
+        }
+    }
+
+    // Creates a map over the finite domain [lo, hi). Lookups outside of it still work, but
+    // domain-wide queries such as domain_length only consider [lo, hi)
+    pub fn with_bounds(lo: K, hi: K, init_val: &'a V) -> Self {
+        assert!(lo < hi, "with_bounds needs lo < hi");
+        let mut imap = IntervalMap::new(init_val);
+        imap.bounds = Some((lo, hi));
+        imap
+    }
+
+    // Same as new, but every insert that overwrites a differing non-default value is recorded.
+    // The records are available through conflicts
+    pub fn new_logging(init_val: &'a V) -> Self {
+        let mut imap = IntervalMap::new(init_val);
+        imap.conflicts = Some(vec![]);
+        imap
+    }
+
+    // Same as new, but the default is computed by f. The anchor needs its value up front, so
+    // f is called exactly once, during construction
+    pub fn with_default_fn<F>(f: F) -> Self
+    where
+        F: FnOnce() -> &'a V,
+    {
+        IntervalMap::new(f())
+    }
+
+    // Builds a map from ranges that are expected to be disjoint. Unlike repeated inserts,
+    // which let later ranges overwrite earlier ones, this fails on the first overlap
+    pub fn try_from_ranges<I>(init_val: &'a V, ranges: I) -> Result<Self, IntervalError>
+    where
+        I: Iterator<Item = (K, K, &'a V)>,
+    {
+        let ranges: Vec<(K, K, &'a V)> = ranges.collect();
+        if !find_overlaps(&ranges).is_empty() {
+            return Err(IntervalError::Overlap);
+        }
+
+        let mut imap = IntervalMap::new(init_val);
+        for (begin, end, val) in ranges {
+            imap.insert(begin, end, val);
+        }
+        Ok(imap)
+    }
+
+    // Builds a map from strictly ascending change points, each meaning "from this key on the
+    // value is X". The first event provides the value of the anchor, so its key has to be
+    // K::minimum()
+    pub fn from_events<I>(events: I) -> Self
+    where
+        I: IntoIterator<Item = (K, &'a V)>,
+    {
+        let mut events = events.into_iter();
+        let (first_key, init_val) = events.next().expect("from_events needs at least one event");
+        assert!(
+            first_key == K::minimum(),
+            "from_events needs the first event at K::minimum"
+        );
+        let mut imap = IntervalMap::new(init_val);
+        let mut prev_key = first_key;
+        for (key, val) in events {
+            assert!(
+                key > prev_key,
+                "from_events needs strictly ascending events"
+            );
+            imap.m_map.insert(key, val);
+            prev_key = key;
+        }
+        imap.coalesce();
+        imap
     }
 
     /* This was the exercise */
     pub fn insert(&mut self, begin_key: K, end_key: K, val: &'a V) {
+        if begin_key < end_key && self.conflicts.is_some() {
+            self.log_conflicts(begin_key, end_key, val);
+        }
+        self.assign(begin_key, end_key, val);
+    }
+
+    // Same as insert, but never logs conflicts. Used by remove and the mutators that only
+    // rearrange values already in the map
+    fn assign(&mut self, begin_key: K, end_key: K, val: &'a V) {
+        if begin_key >= end_key {
+            return;
+        }
         let mut before_val = self.get(&begin_key);
         let mut before_key = &begin_key;
         let mut to_delete: Vec<K> = vec![];
 
         let same_value = before_val == val;
 
-        for elem in self.m_map.keys().into_iter() {
+        for elem in self.m_map.keys() {
             if elem < &begin_key {
                 continue;
             }
@@ -76,109 +294,3224 @@ where
         }
 
         if !same_value {
-            self.m_map.insert(begin_key, val);
+            // Continuing the segment left of begin_key needs no boundary of its own
+            match self.m_map.range(..begin_key).next_back() {
+                Some((_, left_val)) if *left_val == val => {
+                    self.m_map.remove(&begin_key);
+                }
+                _ => {
+                    self.m_map.insert(begin_key, val);
+                }
+            }
         }
         if before_val != val {
             self.m_map.insert(end_key, before_val);
         }
+        self.check_invariants();
+    }
+
+    // Resets [begin_key, end_key) back to the default value
+    pub fn remove(&mut self, begin_key: K, end_key: K) {
+        self.assign(begin_key, end_key, self.default);
+    }
+
+    // Resets the whole map back to the default value
+    pub fn clear(&mut self) {
+        self.m_map.clear();
+        self.m_map.insert(K::minimum(), self.default);
+        self.check_invariants();
     }
 
     // Always returns something
     pub fn get(&self, key: &K) -> &'a V {
-        // Try to find the key
-        let sorted_keys = self.m_map.keys().collect::<Vec<_>>();
-        let idx = self.find_index(&sorted_keys, key);
-        return self.m_map.get(sorted_keys.get(idx).unwrap()).unwrap();
-    }
-
-    // Does a binary search for the value => Returns the biggest value in the map that is
-    // smaller than the key
-    fn find_index(&self, keys: &Vec<&K>, key: &K) -> usize {
-        let mut low = 0;
-        let mut high = (keys.len() - 1) as i32;
-        while low <= high {
-            let mid = low + ((high - low) / 2);
-            if keys[mid as usize] < key {
-                low = mid + 1
-            } else if keys[mid as usize] > key {
-                high = mid - 1
+        self.raw_entry(key).1
+    }
+
+    // Iterates over the coalesced segments as (start, end, value). Only the last one has no end
+    pub fn iter_intervals(&self) -> impl Iterator<Item = Segment<'a, K, V>> + '_ {
+        let mut entries = self.m_map.iter().peekable();
+        std::iter::from_fn(move || {
+            let (start, val) = entries.next()?;
+            while entries.next_if(|(_, next)| *next == val).is_some() {}
+            let end = entries.peek().map(|(key, _)| **key);
+            Some((*start, end, *val))
+        })
+    }
+
+    // Combines two maps into a new one. On every piece where neither map changes value
+    // the result holds f(self_value, other_value)
+    pub fn merge<F>(&self, other: &Self, f: F) -> Self
+    where
+        F: Fn(&'a V, &'a V) -> &'a V,
+    {
+        let m_map = self
+            .zip_with(other)
+            .map(|(key, left, right)| (key, f(left, right)))
+            .collect();
+        let mut merged = IntervalMap {
+            m_map,
+            default: f(self.default, other.default),
+            bounds: self.bounds,
+            conflicts: None,
+        };
+        merged.coalesce();
+        merged
+    }
+
+    // Same as merge, but folds other into self instead of building a new map
+    pub fn merge_into<F>(&mut self, other: &Self, f: F)
+    where
+        F: Fn(&'a V, &'a V) -> &'a V,
+    {
+        // Cut self at every boundary of other, so each stored segment faces a single other value
+        let cuts: Vec<(K, &'a V)> = other
+            .m_map
+            .keys()
+            .filter(|key| !self.m_map.contains_key(key))
+            .map(|key| (*key, self.lookup(key).1))
+            .collect();
+        self.m_map.extend(cuts);
+
+        let mut other_iter = other.m_map.iter().peekable();
+        let mut other_val = other.lookup(&K::minimum()).1;
+        for (key, val) in self.m_map.iter_mut() {
+            while let Some((other_key, next_val)) = other_iter.peek() {
+                if *other_key > key {
+                    break;
+                }
+                other_val = next_val;
+                other_iter.next();
+            }
+            *val = f(val, other_val);
+        }
+        self.default = f(self.default, other.default);
+        self.coalesce();
+    }
+
+    // Exports the map as its change points: the anchor plus every boundary where the value
+    // changes. This is the inverse of from_events
+    pub fn to_events(&self) -> Vec<(K, &'a V)> {
+        let mut prev: Option<&'a V> = None;
+        self.m_map
+            .iter()
+            .filter(|(_, val)| {
+                let changed = prev != Some(**val);
+                prev = Some(**val);
+                changed
+            })
+            .map(|(key, val)| (*key, *val))
+            .collect()
+    }
+
+    // Assuming pred holds for a leading run of segments and fails for the rest, returns the
+    // boundary where it flips from true to false. None if it never flips
+    pub fn partition_point<F>(&self, pred: F) -> Option<K>
+    where
+        F: Fn(&V) -> bool,
+    {
+        let events = self.to_events();
+        let idx = events.partition_point(|(_, val)| pred(val));
+        if idx == 0 || idx == events.len() {
+            return None;
+        }
+        Some(events[idx].0)
+    }
+
+    // Run-length encodes the window [lo, hi) as (length, value) runs. The first and last run
+    // are clipped to the window
+    pub fn run_lengths_in(&self, lo: K, hi: K) -> Vec<(K::Output, &'a V)>
+    where
+        K: Sub,
+    {
+        self.segments_in(lo, hi)
+            .into_iter()
+            .map(|(start, end, val)| (end - start, val))
+            .collect()
+    }
+
+    // Exchanges the contents of the windows a and b. Both are [start, end) pairs that need to
+    // be equally wide and must not overlap
+    pub fn swap_ranges(&mut self, a: (K, K), b: (K, K))
+    where
+        K: Add<Output = K> + Sub<Output = K>,
+    {
+        debug_assert!(
+            a.1 - a.0 == b.1 - b.0,
+            "swap_ranges needs equally wide windows"
+        );
+        debug_assert!(
+            a.1 <= b.0 || b.1 <= a.0,
+            "swap_ranges needs disjoint windows"
+        );
+
+        let a_segments = self.segments_in(a.0, a.1);
+        let b_segments = self.segments_in(b.0, b.1);
+        for (start, end, val) in a_segments {
+            self.assign(b.0 + (start - a.0), b.0 + (end - a.0), val);
+        }
+        for (start, end, val) in b_segments {
+            self.assign(a.0 + (start - b.0), a.0 + (end - b.0), val);
+        }
+        self.coalesce();
+    }
+
+    // Assigns val to every region that still holds the default value
+    pub fn fill_gaps(&mut self, val: &'a V) {
+        let default = self.default;
+        for stored in self.m_map.values_mut() {
+            if *stored == default {
+                *stored = val;
+            }
+        }
+        self.coalesce();
+    }
+
+    // Returns every boundary where the value changes as (key, value before, value after)
+    pub fn transitions(&self) -> impl Iterator<Item = (K, &'a V, &'a V)> + '_ {
+        self.m_map
+            .values()
+            .zip(self.m_map.iter().skip(1))
+            .filter(|(before, (_, after))| before != after)
+            .map(|(before, (key, after))| (*key, *before, *after))
+    }
+
+    // Same as transitions, but only for the boundaries strictly inside [lo, hi)
+    pub fn transitions_in(&self, lo: K, hi: K) -> impl Iterator<Item = (K, &'a V, &'a V)> + '_ {
+        let inside = move || {
+            self.m_map
+                .range((Excluded(lo), Unbounded))
+                .take_while(move |(key, _)| **key < hi)
+        };
+        std::iter::once(self.lookup(&lo).1)
+            .chain(inside().map(|(_, val)| *val))
+            .zip(inside())
+            .filter(|(before, (_, after))| before != *after)
+            .map(|(before, (key, after))| (*key, before, *after))
+    }
+
+    // Returns the segment holding the greatest value. Ties go to the earliest segment
+    pub fn max_segment(&self) -> (K, Option<K>, &'a V)
+    where
+        V: Ord,
+    {
+        self.iter_intervals()
+            .reduce(|best, seg| if seg.2 > best.2 { seg } else { best })
+            .expect(ANCHOR_MISSING)
+    }
+
+    // Returns the segment holding the smallest value. Ties go to the earliest segment
+    pub fn min_segment(&self) -> (K, Option<K>, &'a V)
+    where
+        V: Ord,
+    {
+        self.iter_intervals()
+            .reduce(|best, seg| if seg.2 < best.2 { seg } else { best })
+            .expect(ANCHOR_MISSING)
+    }
+
+    // Returns the value of the segment left of the one containing key. The first segment has
+    // no left neighbour, so it returns its own value
+    pub fn value_before(&self, key: &K) -> &'a V {
+        let val = self.get(key);
+        self.m_map
+            .range(..=*key)
+            .rev()
+            .map(|(_, before)| *before)
+            .find(|before| *before != val)
+            .unwrap_or(val)
+    }
+
+    // Returns the value of the segment right of the one containing key. The last segment has
+    // no right neighbour, so it returns its own value
+    pub fn value_after(&self, key: &K) -> &'a V {
+        let val = self.get(key);
+        self.m_map
+            .range((Excluded(*key), Unbounded))
+            .map(|(_, after)| *after)
+            .find(|after| *after != val)
+            .unwrap_or(val)
+    }
+
+    fn log_conflicts(&mut self, begin_key: K, end_key: K, val: &'a V) {
+        let overwritten: Vec<Conflict<'a, K, V>> = self
+            .segments_in(begin_key, end_key)
+            .into_iter()
+            .filter(|(_, _, old_val)| *old_val != self.default && *old_val != val)
+            .map(|(start, end, old_val)| (start, end, old_val, val))
+            .collect();
+        if let Some(log) = self.conflicts.as_mut() {
+            log.extend(overwritten);
+        }
+    }
+
+    // Returns the bounded domain, panicking for maps that were not built with with_bounds
+    fn domain(&self) -> (K, K) {
+        self.bounds
+            .expect("this query needs a map built with IntervalMap::with_bounds")
+    }
+
+    // Returns the size of the bounded domain. Panics if the map was not built with with_bounds
+    pub fn domain_length(&self) -> K::Output
+    where
+        K: Sub,
+    {
+        let (lo, hi) = self.domain();
+        hi - lo
+    }
+
+    // Returns how much of the bounded domain still holds the default value. Panics if the map
+    // was not built with with_bounds
+    pub fn default_length(&self) -> K::Output
+    where
+        K: Sub,
+        K::Output: Sum,
+    {
+        let (lo, hi) = self.domain();
+        self.segments_in(lo, hi)
+            .into_iter()
+            .filter(|(_, _, val)| *val == self.default)
+            .map(|(start, end, _)| end - start)
+            .sum()
+    }
+
+    // Folds over the coalesced segments, passing each segment's start, end and value
+    pub fn fold_segments<B, F>(&self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, K, Option<K>, &V) -> B,
+    {
+        self.iter_intervals()
+            .fold(init, |acc, (start, end, val)| f(acc, start, end, val))
+    }
+
+    // Iterates over every pair of consecutive segments, like slice::windows(2)
+    pub fn segment_windows(
+        &self,
+    ) -> impl Iterator<Item = (Segment<'a, K, V>, Segment<'a, K, V>)> + '_ {
+        self.iter_intervals().zip(self.iter_intervals().skip(1))
+    }
+
+    // Returns the length-weighted p-th percentile value over the bounded domain, where every
+    // value counts for the length of its segments. p is clamped to [0, 1]. Panics if the map
+    // was not built with with_bounds
+    pub fn value_percentile(&self, p: f64) -> &'a V
+    where
+        V: Ord,
+        K: Sub,
+        K::Output: Into<f64>,
+    {
+        let (lo, hi) = self.domain();
+        let mut weighted: Vec<(&'a V, f64)> = self
+            .segments_in(lo, hi)
+            .into_iter()
+            .map(|(start, end, val)| (val, (end - start).into()))
+            .collect();
+        weighted.sort_by(|left, right| left.0.cmp(right.0));
+
+        let total: f64 = weighted.iter().map(|(_, length)| length).sum();
+        let target = p.clamp(0.0, 1.0) * total;
+        let mut covered = 0.0;
+        for (val, length) in &weighted {
+            covered += length;
+            if covered >= target {
+                return val;
+            }
+        }
+        weighted.last().unwrap().0
+    }
+
+    // Moves the content of [from.0, from.1) so it starts at to_start, resetting the vacated
+    // window to the default. Source and destination are allowed to overlap
+    pub fn move_range(&mut self, from: (K, K), to_start: K)
+    where
+        K: Add<Output = K> + Sub<Output = K>,
+    {
+        let segments = self.segments_in(from.0, from.1);
+        self.remove(from.0, from.1);
+        for (start, end, val) in segments {
+            self.assign(to_start + (start - from.0), to_start + (end - from.0), val);
+        }
+    }
+
+    // Panics if the map breaks one of its invariants. Every lookup relies on the anchor at
+    // K::minimum(): it guarantees that each key has a segment to fall into, so get can always
+    // return something. Next to that, boundaries are strictly ascending and every boundary
+    // changes the value, so the map stays canonical
+    #[cfg(debug_assertions)]
+    pub fn assert_well_formed(&self) {
+        assert!(
+            self.m_map.keys().next() == Some(&K::minimum()),
+            "{}",
+            ANCHOR_MISSING
+        );
+        assert!(
+            self.m_map
+                .keys()
+                .zip(self.m_map.keys().skip(1))
+                .all(|(before, after)| before < after),
+            "IntervalMap boundaries are not strictly ascending"
+        );
+        assert!(
+            self.m_map
+                .values()
+                .zip(self.m_map.values().skip(1))
+                .all(|(before, after)| before != after),
+            "IntervalMap holds a boundary that does not change the value"
+        );
+    }
+
+    // Like insert, but every piece of [begin_key, end_key) gets blend(old value, val) instead of
+    // being overwritten with val. Blending keeps the old values in, so it logs no conflicts
+    pub fn insert_blend<F>(&mut self, begin_key: K, end_key: K, val: &'a V, blend: F)
+    where
+        F: Fn(&V, &V) -> &'a V,
+    {
+        for (start, end, old_val) in self.segments_in(begin_key, end_key) {
+            self.assign(start, end, blend(old_val, val));
+        }
+    }
+
+    // Returns every stored boundary with its value in ascending order, the anchor included
+    pub fn to_vec(&self) -> Vec<(K, &'a V)> {
+        self.m_map.iter().map(|(key, val)| (*key, *val)).collect()
+    }
+
+    // Returns how many different values the map holds, the default included
+    pub fn distinct_value_count(&self) -> usize
+    where
+        V: Eq + Hash,
+    {
+        self.m_map.values().collect::<HashSet<_>>().len()
+    }
+
+    // Returns the value at key along with the bounds of the segment containing it
+    pub fn context(&self, key: &K) -> Context<'a, K, V> {
+        let (start, value) = self.lookup(key);
+        let end = self
+            .m_map
+            .range((Excluded(*key), Unbounded))
+            .next()
+            .map(|(next, _)| *next);
+        Context { value, start, end }
+    }
+
+    // Resets everything below key to the default value
+    pub fn truncate_before(&mut self, key: K) {
+        self.remove(K::minimum(), key);
+    }
+
+    // Resets everything from key on to the default value
+    pub fn truncate_after(&mut self, key: K) {
+        if key == K::minimum() {
+            return self.clear();
+        }
+        self.m_map.split_off(&key);
+        if self.lookup(&key).1 != self.default {
+            self.m_map.insert(key, self.default);
+        }
+        self.check_invariants();
+    }
+
+    // Returns the area under the map from the start of the bounded domain up to up_to, every
+    // segment contributing value * length. Panics if the map was not built with with_bounds
+    pub fn prefix_sum(&self, up_to: K) -> V::Output
+    where
+        V: Copy + Mul<K::Output>,
+        V::Output: Sum,
+        K: Sub,
+    {
+        let (lo, _) = self.domain();
+        self.integrate(lo, up_to)
+    }
+
+    // Returns the area under the map over [lo, hi), every segment contributing value * length
+    // for the part of it inside the window
+    pub fn integrate(&self, lo: K, hi: K) -> V::Output
+    where
+        V: Copy + Mul<K::Output>,
+        V::Output: Sum,
+        K: Sub,
+    {
+        self.segments_in(lo, hi)
+            .into_iter()
+            .map(|(start, end, val)| *val * (end - start))
+            .sum()
+    }
+
+    // Returns the bounds of the segment holding target that is closest to key, measured from key
+    // to the nearest boundary of that segment. Ties go to the segment before key
+    pub fn nearest_with_value(&self, key: &K, target: &V) -> Option<(K, Option<K>)>
+    where
+        K: Sub,
+        K::Output: Ord,
+    {
+        let (start, val) = self.lookup(key);
+        if val == target {
+            return Some((start, self.context(key).end));
+        }
+
+        let distance = |(seg_start, seg_end): (K, Option<K>)| {
+            if seg_start > *key {
+                seg_start - *key
+            } else {
+                *key - seg_end.unwrap()
+            }
+        };
+        self.iter_intervals()
+            .filter(|(_, _, val)| *val == target)
+            .map(|(seg_start, seg_end, _)| (seg_start, seg_end))
+            .reduce(|best, seg| {
+                if distance(seg) < distance(best) {
+                    seg
+                } else {
+                    best
+                }
+            })
+    }
+
+    // Returns a closure doing get, for APIs that expect a plain Fn(K) -> V lookup
+    pub fn as_fn(&self) -> impl Fn(K) -> &'a V + '_ {
+        move |key| self.get(&key)
+    }
+
+    // Clears the window [window.0, window.1) and puts the content replacement has there in its
+    // place. Default regions of replacement are gaps, so they keep the default of self
+    pub fn splice(&mut self, window: (K, K), replacement: &IntervalMap<'a, K, V>) {
+        self.remove(window.0, window.1);
+        for (start, end, val) in replacement.segments_in(window.0, window.1) {
+            if val != replacement.default {
+                self.assign(start, end, val);
+            }
+        }
+    }
+
+    // Yields every integer key in [lo, hi) with its value, walking the segments instead of
+    // looking every key up on its own
+    pub fn points_in(&self, lo: K, hi: K) -> impl Iterator<Item = (K, &'a V)>
+    where
+        K: Add<Output = K> + One,
+    {
+        self.segments_in(lo, hi)
+            .into_iter()
+            .flat_map(|(start, end, val)| {
+                std::iter::successors(Some(start), move |key| {
+                    let next = *key + K::one();
+                    if next < end {
+                        Some(next)
+                    } else {
+                        None
+                    }
+                })
+                .map(move |key| (key, val))
+            })
+    }
+
+    // Inserts like insert and returns whether that changed the map. It does not when the whole
+    // range already held val
+    pub fn insert_changed(&mut self, begin_key: K, end_key: K, val: &'a V) -> bool {
+        let unchanged = self.holds_only(begin_key, end_key, val);
+        self.insert(begin_key, end_key, val);
+        !unchanged
+    }
+
+    // Returns whether [begin_key, end_key) is empty or holds val throughout
+    fn holds_only(&self, begin_key: K, end_key: K, val: &'a V) -> bool {
+        begin_key >= end_key
+            || (self.get(&begin_key) == val
+                && self
+                    .m_map
+                    .range((Excluded(begin_key), Excluded(end_key)))
+                    .next()
+                    .is_none())
+    }
+
+    // Iterates over the coalesced segments whose value satisfies pred
+    pub fn segments_matching<'q, F>(
+        &'q self,
+        pred: F,
+    ) -> impl Iterator<Item = Segment<'q, K, V>> + 'q
+    where
+        F: Fn(&V) -> bool + 'q,
+    {
+        self.iter_intervals().filter(move |(_, _, val)| pred(val))
+    }
+
+    // Stretches the segment before key forward so that it covers key, filling the default gap
+    // in between. Nothing happens if key already lies in a non-default segment, or if there is
+    // no segment before the gap. For key == K::maximum() the segment takes over the whole gap
+    pub fn extend_to(&mut self, key: K)
+    where
+        K: Add<Output = K> + One + Max,
+    {
+        let (gap_start, val) = self.lookup(&key);
+        if val != self.default {
+            return;
+        }
+        if let Some((_, before_val)) = self.m_map.range(..gap_start).next_back() {
+            let before_val = *before_val;
+            if key == K::maximum() {
+                // No key follows K::maximum(), so the gap ends nowhere and is dropped entirely
+                self.m_map.remove(&gap_start);
+                self.check_invariants();
             } else {
-                return mid as usize;
+                self.assign(gap_start, key + K::one(), before_val);
             }
         }
+    }
+
+    // Returns every coalesced segment as (start, end, run index, value), counting from 0
+    pub fn runs(&self) -> Vec<(K, Option<K>, usize, &'a V)> {
+        self.iter_intervals()
+            .enumerate()
+            .map(|(idx, (start, end, val))| (start, end, idx, val))
+            .collect()
+    }
+
+    // Returns whether every key in [lo, hi) holds a non-default value, i.e. the window is
+    // partitioned without gaps
+    pub fn covers_exactly(&self, lo: K, hi: K) -> bool {
+        self.segments_in(lo, hi)
+            .iter()
+            .all(|(_, _, val)| *val != self.default)
+    }
 
-        if high < 0 {
-            return 0;
-        } else if low as usize > keys.len() - 1 {
-            return keys.len() - 1;
+    // Cyclically shifts the content of the bounded domain by by, wrapping whatever moves past
+    // the upper bound around to the lower bound. Panics if the map was not built with
+    // with_bounds
+    pub fn rotate(&mut self, by: K)
+    where
+        K: Add<Output = K> + Sub<Output = K> + Rem<Output = K>,
+    {
+        let (lo, hi) = self.domain();
+        let width = hi - lo;
+        let rest = by % width;
+        let shift = if rest < K::minimum() - K::minimum() {
+            rest + width
         } else {
-            return if low < high {
-                low as usize
+            rest
+        };
+        // Keys at least this far above lo wrap around. Comparing offsets from lo instead of
+        // the shifted keys keeps every step inside [lo, hi)
+        let wrap = width - shift;
+
+        for (start, end, val) in self.segments_in(lo, hi) {
+            if end - lo <= wrap {
+                self.assign(start + shift, end + shift, val);
+            } else if start - lo >= wrap {
+                self.assign(start - wrap, end - wrap, val);
             } else {
-                high as usize
-            };
+                self.assign(start + shift, hi, val);
+                self.assign(lo, end - wrap, val);
+            }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    #[test]
-    fn it_works() {
-        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
-        assert_eq!(test_map.get(&10), &'a');
+    // Multiplies every boundary except the anchor by factor, and the bounds of a map built with
+    // with_bounds along with them. factor has to be positive, and the scaled keys may not
+    // overflow the key range
+    pub fn scale(&mut self, factor: K)
+    where
+        K: CheckedMul + One,
+    {
+        assert!(factor >= K::one(), "scale needs a positive factor");
+        let scale_key = |key: K| {
+            if key == K::minimum() {
+                key
+            } else {
+                key.checked_mul(factor)
+                    .expect("scale overflowed the key range")
+            }
+        };
+        let scaled: Vec<(K, &'a V)> = self
+            .m_map
+            .iter()
+            .map(|(key, val)| (scale_key(*key), *val))
+            .collect();
+        self.bounds = self.bounds.map(|(lo, hi)| (scale_key(lo), scale_key(hi)));
+        // Refilled in place, so a map made with new_small keeps its backing
+        self.m_map.clear();
+        self.m_map.extend(scaled);
+        self.check_invariants();
+    }
 
-        test_map.insert(10, 20, &'b');
-        assert_eq!(test_map.get(&11), &'b');
-        assert_eq!(test_map.get(&20), &'a');
+    // Clones every referenced value into an OwnedIntervalMap, which no longer depends on the
+    // lifetime of the value sources
+    pub fn into_owned(self) -> OwnedIntervalMap<K, V>
+    where
+        V: Clone,
+    {
+        OwnedIntervalMap {
+            m_map: self
+                .m_map
+                .into_iter()
+                .map(|(key, val)| (key, val.clone()))
+                .collect(),
+            default: self.default.clone(),
+        }
+    }
 
-        test_map.insert(25, 30, &'c');
-        assert_eq!(test_map.get(&10), &'b');
-        assert_eq!(test_map.get(&20), &'a');
-        assert_eq!(test_map.get(&26), &'c');
-        assert_eq!(test_map.get(&30), &'a');
+    // Returns the bounds of the earliest default region after the first boundary that is at
+    // least width wide. The region running off the top of the key range always fits. The
+    // region before the first boundary is not a slot between content, so it is skipped
+    pub fn first_gap_at_least(&self, width: K::Output) -> Option<(K, Option<K>)>
+    where
+        K: Sub,
+        K::Output: Ord,
+    {
+        self.iter_intervals()
+            .filter(|(_, _, val)| *val == self.default)
+            .find(|(start, end, _)| match end {
+                Some(_) if *start == K::minimum() => false,
+                Some(end) => *end - *start >= width,
+                None => true,
+            })
+            .map(|(start, end, _)| (start, end))
     }
 
-    #[test]
-    fn overlapping() {
-        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
-        test_map.insert(10, 20, &'b');
-        test_map.insert(15, 25, &'c');
+    // Removes like remove and returns whether any non-default content was reset
+    pub fn remove_changed(&mut self, begin_key: K, end_key: K) -> bool {
+        let unchanged = self.holds_only(begin_key, end_key, self.default);
+        self.remove(begin_key, end_key);
+        !unchanged
+    }
 
-        assert_eq!(test_map.get(&12), &'b');
-        assert_eq!(test_map.get(&15), &'c');
-        assert_eq!(test_map.get(&20), &'c');
-        assert_eq!(test_map.get(&25), &'a');
+    // Returns the boundary nearest to key, leaving out the anchor. Ties go to the lower
+    // boundary. None if the map has no boundaries besides the anchor
+    pub fn closest_boundary(&self, key: &K) -> Option<K>
+    where
+        K: CheckedSub,
+    {
+        let below = self
+            .m_map
+            .range((Excluded(K::minimum()), Included(*key)))
+            .next_back()
+            .map(|(below, _)| *below);
+        let above = self
+            .m_map
+            .range((Excluded(*key), Unbounded))
+            .next()
+            .map(|(above, _)| *above);
+        match (below, above) {
+            // The two distances add up to above - below, so at most one of them overflows, and
+            // that one is the larger
+            (Some(below), Some(above)) => match (key.checked_sub(below), above.checked_sub(*key)) {
+                (Some(to_below), Some(to_above)) if to_above < to_below => Some(above),
+                (None, _) => Some(above),
+                _ => Some(below),
+            },
+            (Some(below), None) => Some(below),
+            (None, above) => above,
+        }
     }
 
-    #[test]
-    fn intertwined() {
-        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
-        test_map.insert(10, 20, &'b');
-        test_map.insert(20, 30, &'c');
+    // Returns a terse snapshot like "[-inf,10)=a;[10,20)=b;[20,inf)=a" for tests to compare
+    // against. The unbounded ends are written as -inf and inf
+    pub fn debug_dump(&self) -> String
+    where
+        K: fmt::Display,
+        V: fmt::Display,
+    {
+        self.iter_intervals()
+            .map(|(start, end, val)| {
+                let start = if start == K::minimum() {
+                    String::from("-inf")
+                } else {
+                    start.to_string()
+                };
+                let end = end.map_or(String::from("inf"), |end| end.to_string());
+                format!("[{},{})={}", start, end, val)
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    }
 
-        assert_eq!(test_map.get(&10), &'b');
-        assert_eq!(test_map.get(&20), &'c');
-        assert_eq!(test_map.get(&25), &'c');
-        assert_eq!(test_map.get(&30), &'a');
+    // Overlays two maps where the greater value wins wherever they differ
+    pub fn overlay_by_priority(&self, other: &Self) -> Self
+    where
+        V: Ord,
+    {
+        self.merge(other, std::cmp::max)
     }
 
-    #[test]
-    fn multiple_overlapping() {
-        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
-        test_map.insert(20, 25, &'b');
-        test_map.insert(10, 25, &'c');
-        test_map.insert(22, 50, &'c');
-        assert_eq!(test_map.get(&5), &'a');
-        assert_eq!(test_map.get(&10), &'c');
-        assert_eq!(test_map.get(&20), &'c');
-        assert_eq!(test_map.get(&51), &'a');
-        for (k, v) in &test_map.m_map {
-            println!("{} - {}", k, v);
+    // Returns the number of boundaries where the value changes
+    pub fn transition_count(&self) -> usize {
+        self.transitions().count()
+    }
+
+    // Holds the last assigned value: the default region at the end of the bounded domain takes
+    // the value of the segment before it. Panics if the map was not built with with_bounds
+    pub fn fill_forward(&mut self) {
+        let (lo, hi) = self.domain();
+        let segments = self.segments_in(lo, hi);
+        if let [.., (_, _, last_val), (tail_start, _, tail_val)] = segments[..] {
+            if tail_val == self.default {
+                self.assign(tail_start, hi, last_val);
+            }
+        }
+    }
+
+    // Returns the overwrites recorded so far as (start, end, old, new). Always empty for maps
+    // that were not built with new_logging
+    pub fn conflicts(&self) -> &[Conflict<'a, K, V>] {
+        self.conflicts.as_deref().unwrap_or(&[])
+    }
+
+    // Same as iter_intervals, but stops at end: the segment containing end is cut off there and
+    // later segments are left out, so the final segment is never unbounded
+    pub fn iter_intervals_to(&self, end: K) -> impl Iterator<Item = (K, K, &'a V)> + '_ {
+        self.iter_intervals()
+            .take_while(move |(start, _, _)| *start < end)
+            .map(move |(start, seg_end, val)| {
+                let seg_end = seg_end.map_or(end, |seg_end| std::cmp::min(seg_end, end));
+                (start, seg_end, val)
+            })
+    }
+
+    // Merges consecutive segments for which key_fn gives the same group, yielding the merged
+    // extent together with that group
+    pub fn group_by<'q, G, F>(&'q self, key_fn: F) -> impl Iterator<Item = (K, Option<K>, G)> + 'q
+    where
+        F: Fn(&V) -> G + 'q,
+        G: Eq + 'q,
+    {
+        let mut segments = self
+            .iter_intervals()
+            .map(move |(start, end, val)| (start, end, key_fn(val)))
+            .peekable();
+        std::iter::from_fn(move || {
+            let (start, mut end, group) = segments.next()?;
+            while let Some((_, next_end, _)) = segments.next_if(|(_, _, next)| *next == group) {
+                end = next_end;
+            }
+            Some((start, end, group))
+        })
+    }
+
+    // Same as iter_intervals, but the final segment ends at K::maximum(), so every segment has
+    // a concrete end. A segment starting at K::maximum() itself has no extent and is left out
+    pub fn iter_bounded(&self) -> impl Iterator<Item = (K, K, &'a V)> + '_
+    where
+        K: Max,
+    {
+        self.iter_intervals_to(K::maximum())
+    }
+
+    // Returns the span from the first to the last key holding a non-default value, or None if
+    // the whole map is default. A non-default final segment is unbounded, so the span then ends
+    // at its start, the largest boundary
+    pub fn assigned_extent(&self) -> Option<(K, K)> {
+        let mut assigned = self
+            .iter_intervals()
+            .filter(|(_, _, val)| *val != self.default);
+        let first = assigned.next()?;
+        let (last_start, last_end, _) = assigned.last().unwrap_or(first);
+        Some((first.0, last_end.unwrap_or(last_start)))
+    }
+
+    // Groups the coalesced segments by value, each value mapping to all ranges it covers in
+    // ascending order
+    pub fn ranges_by_value(&self) -> HashMap<&'a V, Vec<(K, Option<K>)>>
+    where
+        V: Eq + Hash,
+    {
+        let mut ranges: HashMap<&'a V, Vec<(K, Option<K>)>> = HashMap::new();
+        for (start, end, val) in self.iter_intervals() {
+            ranges.entry(val).or_default().push((start, end));
+        }
+        ranges
+    }
+
+    // Returns the stored boundary key where the segment containing key starts, together with
+    // its value. The key is borrowed from the map rather than copied
+    pub fn raw_entry(&self, key: &K) -> (&K, &'a V) {
+        let (start, val) = self.m_map.range(..=*key).next_back().expect(ANCHOR_MISSING);
+        (start, val)
+    }
+
+    // Inserts val over [begin_key, begin_key + width). A zero or negative width inserts nothing
+    pub fn insert_width(&mut self, begin_key: K, width: <K as Sub>::Output, val: &'a V)
+    where
+        K: Sub + Add<<K as Sub>::Output, Output = K>,
+    {
+        let end_key = begin_key + width;
+        if end_key > begin_key {
+            self.insert(begin_key, end_key, val);
+        }
+    }
+
+    // Makes all segments with equal values point at the same reference, the first one found
+    // in ascending key order. Relies on V: Eq to treat equal values as interchangeable
+    pub fn intern_values(&mut self)
+    where
+        V: Eq,
+    {
+        let mut seen: Vec<&'a V> = vec![];
+        for val in self.m_map.values_mut() {
+            match seen.iter().find(|interned| **interned == *val) {
+                Some(interned) => *val = interned,
+                None => seen.push(val),
+            }
         }
     }
 
+    // Returns the n-th coalesced segment in ascending order, counting from 0
+    pub fn nth_segment(&self, n: usize) -> Option<Segment<'a, K, V>> {
+        self.iter_intervals().nth(n)
+    }
+
+    // Looks up an ascending slice of keys in one pass over the map, instead of searching for
+    // every key on its own
+    pub fn get_sorted_slice(&self, keys: &[K]) -> Vec<&'a V> {
+        debug_assert!(
+            keys.windows(2).all(|pair| pair[0] <= pair[1]),
+            "get_sorted_slice needs ascending keys"
+        );
+
+        let mut entries = self.m_map.iter().peekable();
+        let mut val = self.default;
+        keys.iter()
+            .map(|key| {
+                while let Some((_, next_val)) = entries.next_if(|(start, _)| *start <= key) {
+                    val = next_val;
+                }
+                val
+            })
+            .collect()
+    }
+
+    // Smooths the map with a majority filter. The span between the first and last boundary is
+    // cut into consecutive windows, each running from its start to the first boundary at least
+    // width further on (or to the last boundary). Windows therefore start and end on existing
+    // boundaries, so a boundary without noise around it stays put. Each window takes the value
+    // covering most of it. Ties go to the value found first
+    pub fn keep_dominant(&mut self, window: <K as Sub>::Output)
+    where
+        K: Sub + Add<<K as Sub>::Output, Output = K>,
+        <K as Sub>::Output: Add<Output = <K as Sub>::Output> + Copy + Ord,
+    {
+        let boundaries: Vec<K> = self.m_map.keys().skip(1).copied().collect();
+        let (lo, hi) = match (boundaries.first(), boundaries.last()) {
+            (Some(lo), Some(hi)) if lo < hi => (*lo, *hi),
+            _ => return,
+        };
+        assert!(lo + window > lo, "keep_dominant needs a positive window");
+
+        let mut smoothed: Vec<(K, K, &'a V)> = vec![];
+        let mut ends = boundaries.iter().copied();
+        let mut start = lo;
+        while start < hi {
+            let end = ends.find(|key| *key - start >= window).unwrap_or(hi);
+            let mut coverage: Vec<(&'a V, <K as Sub>::Output)> = vec![];
+            for (seg_start, seg_end, val) in self.segments_in(start, end) {
+                let length = seg_end - seg_start;
+                match coverage.iter_mut().find(|(covered, _)| *covered == val) {
+                    Some((_, total)) => *total = *total + length,
+                    None => coverage.push((val, length)),
+                }
+            }
+            let dominant = coverage
+                .into_iter()
+                .reduce(|best, next| if next.1 > best.1 { next } else { best })
+                .expect("windows are never empty");
+            smoothed.push((start, end, dominant.0));
+            start = end;
+        }
+
+        for (start, end, val) in smoothed {
+            self.assign(start, end, val);
+        }
+    }
+
+    // Iterates over consecutive pairs of non-default segments. The flag tells whether the two
+    // touch, so the value was merely relabelled (true), or a default gap lies between them
+    // (false)
+    pub fn segment_pairs_flagged(
+        &self,
+    ) -> impl Iterator<Item = (Segment<'a, K, V>, Segment<'a, K, V>, bool)> + '_ {
+        let assigned = move || {
+            self.iter_intervals()
+                .filter(move |(_, _, val)| *val != self.default)
+        };
+        assigned()
+            .zip(assigned().skip(1))
+            .map(|(left, right)| (left, right, left.1 == Some(right.0)))
+    }
+
+    // Treats the value at every segment start as a control point and linearly interpolates
+    // between the start of the segment containing key and the start of the next one. The last
+    // segment has no next control point, so it keeps its value. The anchor segment starts at
+    // K::minimum() rather than at a real control point, so it keeps its value as well
+    pub fn sample_linear(&self, key: &K) -> f64
+    where
+        K: Into<f64>,
+        V: Into<f64> + Copy,
+    {
+        let ctx = self.context(key);
+        let from: f64 = (*ctx.value).into();
+        match ctx.end {
+            Some(end) if ctx.start != K::minimum() => {
+                let to: f64 = (*self.get(&end)).into();
+                let (start, end, key): (f64, f64, f64) =
+                    (ctx.start.into(), end.into(), (*key).into());
+                from + (to - from) * (key - start) / (end - start)
+            }
+            _ => from,
+        }
+    }
+
+    // Returns a read-only snapshot backed by a flat array, which is faster to query than the
+    // map itself once no more changes are coming
+    pub fn freeze(&self) -> FrozenIntervalMap<'a, K, V> {
+        FrozenIntervalMap {
+            entries: self.to_vec(),
+        }
+    }
+
+    // Returns how many keys in [lo, hi) hold target
+    pub fn point_count_for(&self, target: &V, lo: K, hi: K) -> K::Output
+    where
+        K: Sub,
+        K::Output: Sum,
+    {
+        self.segments_in(lo, hi)
+            .into_iter()
+            .filter(|(_, _, val)| *val == target)
+            .map(|(start, end, _)| end - start)
+            .sum()
+    }
+
+    // Clears [window.0, window.1) and applies the change points in events to it, each meaning
+    // "from this key on the value is X". Events have to be strictly ascending and lie inside
+    // the window
+    pub fn set_window_events(&mut self, window: (K, K), events: &[(K, &'a V)]) {
+        assert!(
+            events.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "set_window_events needs strictly ascending events"
+        );
+        assert!(
+            events
+                .iter()
+                .all(|(key, _)| window.0 <= *key && *key < window.1),
+            "set_window_events needs events inside the window"
+        );
+
+        self.remove(window.0, window.1);
+        for (idx, (start, val)) in events.iter().enumerate() {
+            let end = events.get(idx + 1).map_or(window.1, |(next, _)| *next);
+            self.assign(*start, end, val);
+        }
+    }
+
+    // Returns the first boundary where a segment holding left is directly followed by one
+    // holding right
+    pub fn boundary_between(&self, left: &V, right: &V) -> Option<K> {
+        self.transitions()
+            .find(|(_, before, after)| *before == left && *after == right)
+            .map(|(key, _, _)| key)
+    }
+
+    // Iterates over the segments overlapping [lo, hi) as (start, end, value), clamped to the
+    // window: the first starts at lo and the last ends at hi
+    pub fn iter_clamped(&self, lo: K, hi: K) -> impl Iterator<Item = (K, K, &'a V)> + '_ {
+        let mut current = if lo < hi {
+            Some((lo, self.lookup(&lo).1))
+        } else {
+            None
+        };
+        let mut boundaries = self
+            .m_map
+            .range((Excluded(lo), Unbounded))
+            .take_while(move |(key, _)| **key < hi)
+            .peekable();
+        std::iter::from_fn(move || {
+            let (start, val) = current?;
+            while boundaries.next_if(|(_, next)| **next == val).is_some() {}
+            match boundaries.next() {
+                Some((key, next_val)) => {
+                    current = Some((*key, *next_val));
+                    Some((start, *key, val))
+                }
+                None => {
+                    current = None;
+                    Some((start, hi, val))
+                }
+            }
+        })
+    }
+
+    // Combines any number of maps in one sweep over all their boundaries. On every piece where
+    // none of the maps changes value, the result holds f applied to all their values there, in
+    // the order of maps
+    pub fn merge_all<'b, F>(maps: &[&'b Self], f: F) -> Self
+    where
+        F: Fn(&[&'a V]) -> &'a V,
+    {
+        assert!(!maps.is_empty(), "merge_all needs at least one map");
+        let keys: BTreeSet<K> = maps
+            .iter()
+            .flat_map(|imap| imap.m_map.keys().copied())
+            .collect();
+
+        let mut cursors: Vec<_> = maps
+            .iter()
+            .map(|imap| imap.m_map.iter().peekable())
+            .collect();
+        let mut current: Vec<&'a V> = maps.iter().map(|imap| imap.default).collect();
+        let mut m_map = SegmentStore::new();
+        for key in keys {
+            for (cursor, val) in cursors.iter_mut().zip(current.iter_mut()) {
+                if let Some((_, next_val)) = cursor.next_if(|(start, _)| **start == key) {
+                    *val = next_val;
+                }
+            }
+            m_map.insert(key, f(&current));
+        }
+
+        let defaults: Vec<&'a V> = maps.iter().map(|imap| imap.default).collect();
+        let mut merged = IntervalMap {
+            m_map,
+            default: f(&defaults),
+            bounds: maps[0].bounds,
+            conflicts: None,
+        };
+        merged.coalesce();
+        merged
+    }
+
+    // Returns whether no non-default content of self overlaps non-default content of other.
+    // Stops walking at the first overlap
+    pub fn is_disjoint_from(&self, other: &Self) -> bool {
+        !self
+            .zip_with(other)
+            .any(|(_, left, right)| left != self.default && right != other.default)
+    }
+
+    // Pairs the value at every point with the value the table has there. A reference to a
+    // pair has nothing to borrow from, so the pairs are owned by the result
+    pub fn project<'b, W>(
+        &self,
+        table: &IntervalMap<'b, K, W>,
+    ) -> OwnedIntervalMap<K, (&'a V, &'b W)>
+    where
+        W: PartialEq,
+    {
+        let mut projected = OwnedIntervalMap {
+            m_map: self
+                .zip_with(table)
+                .map(|(key, val, table_val)| (key, (val, table_val)))
+                .collect(),
+            default: (self.default, table.default),
+        };
+        projected.coalesce();
+        projected
+    }
+
+    // Resets every segment holding target back to the default and returns the ranges that were
+    // reset. Draining the default itself changes nothing and returns no ranges
+    pub fn drain_value(&mut self, target: &V) -> Vec<(K, Option<K>)> {
+        if target == self.default {
+            return Vec::new();
+        }
+        let drained: Vec<(K, Option<K>)> = self
+            .iter_intervals()
+            .filter(|(_, _, val)| *val == target)
+            .map(|(start, end, _)| (start, end))
+            .collect();
+
+        let default = self.default;
+        for val in self.m_map.values_mut() {
+            if *val == target {
+                *val = default;
+            }
+        }
+        self.coalesce();
+        drained
+    }
+
+    // Returns the segment start that contains the key together with its value
+    fn lookup(&self, key: &K) -> (K, &'a V) {
+        let (start, val) = self.raw_entry(key);
+        (*start, val)
+    }
+
+    // Collects iter_clamped, for callers that change the map while walking the segments
+    fn segments_in(&self, lo: K, hi: K) -> Vec<(K, K, &'a V)> {
+        self.iter_clamped(lo, hi).collect()
+    }
+
+    // Walks the boundaries of both maps together. Every key at which either map changes
+    // is yielded with the values both maps have from that key on
+    fn zip_with<'m, 'b, W>(
+        &'m self,
+        other: &'m IntervalMap<'b, K, W>,
+    ) -> impl Iterator<Item = (K, &'a V, &'b W)> + 'm {
+        let mut left = self.m_map.iter().peekable();
+        let mut right = other.m_map.iter().peekable();
+        let (mut left_val, mut right_val) = (None, None);
+
+        std::iter::from_fn(move || {
+            let key = match (left.peek(), right.peek()) {
+                (Some((l, _)), Some((r, _))) => std::cmp::min(**l, **r),
+                (Some((l, _)), None) => **l,
+                (None, Some((r, _))) => **r,
+                (None, None) => return None,
+            };
+            if let Some((_, val)) = left.next_if(|(l, _)| **l == key) {
+                left_val = Some(*val);
+            }
+            if let Some((_, val)) = right.next_if(|(r, _)| **r == key) {
+                right_val = Some(*val);
+            }
+            // Both maps are anchored at K::minimum(), so both sides are known from the first key on
+            Some((
+                key,
+                left_val.expect(ANCHOR_MISSING),
+                right_val.expect(ANCHOR_MISSING),
+            ))
+        })
+    }
+
+    // Removes every boundary that does not change the value, keeping the map canonical
+    fn coalesce(&mut self) {
+        let mut prev: Option<&'a V> = None;
+        self.m_map.retain(|_, val| {
+            let keep = prev != Some(*val);
+            prev = Some(*val);
+            keep
+        });
+        self.check_invariants();
+    }
+
+    // Runs assert_well_formed after mutations in debug builds
+    fn check_invariants(&self) {
+        #[cfg(debug_assertions)]
+        self.assert_well_formed();
+    }
+
+    #[cfg(test)]
+    fn raw_map_mut(&mut self) -> &mut SegmentStore<K, &'a V> {
+        &mut self.m_map
+    }
+}
+
+// Morphological operations for coverage maps, where true marks a covered key
+impl<'a, K> IntervalMap<'a, K, bool>
+where
+    K: Eq + Hash + Copy + Ord + Min + Add<Output = K> + Sub<Output = K>,
+{
+    // Grows every true segment by radius on both sides, merging segments that come to overlap.
+    // The unbounded ends of the map stay where they are, and grown edges stop at the ends of
+    // the key range. Panics on a negative radius
+    pub fn dilate(&mut self, radius: K)
+    where
+        K: Max,
+    {
+        let zero = K::minimum() - K::minimum();
+        assert!(radius >= zero, "dilate needs a non-negative radius");
+        let mut grown: Vec<(K, Option<K>)> = vec![];
+        for (start, end, val) in self.iter_intervals() {
+            if !*val {
+                continue;
+            }
+            let start = if start < K::minimum() + radius {
+                K::minimum()
+            } else {
+                start - radius
+            };
+            // An end growing past K::maximum() covers the rest of the key range
+            let end = end
+                .filter(|end| *end <= K::maximum() - radius)
+                .map(|end| end + radius);
+            match grown.last_mut() {
+                Some((_, last_end)) if last_end.is_none_or(|last_end| start <= last_end) => {
+                    *last_end = match (*last_end, end) {
+                        (Some(last_end), Some(end)) => Some(std::cmp::max(last_end, end)),
+                        _ => None,
+                    };
+                }
+                _ => grown.push((start, end)),
+            }
+        }
+
+        self.m_map.clear();
+        self.m_map.insert(K::minimum(), &false);
+        for (start, end) in grown {
+            self.m_map.insert(start, &true);
+            if let Some(end) = end {
+                self.m_map.insert(end, &false);
+            }
+        }
+        self.coalesce();
+    }
+
+    // Shrinks every true segment by radius on both sides. Segments narrower than twice the
+    // radius disappear. Panics on a negative radius
+    pub fn erode(&mut self, radius: K)
+    where
+        K: Max,
+    {
+        self.invert();
+        self.dilate(radius);
+        self.invert();
+    }
+
+    fn invert(&mut self) {
+        for val in self.m_map.values_mut() {
+            *val = if **val { &false } else { &true };
+        }
+    }
+}
+
+// Comparisons for float-valued maps, where exact equality is rarely useful
+impl<'a, K> IntervalMap<'a, K, f64>
+where
+    K: Eq + Hash + Copy + Ord + Min,
+{
+    // Returns whether both maps hold values within tol of each other at every key. The maps
+    // are walked over the union of their boundaries, so they don't need to be cut the same way
+    pub fn approx_eq(&self, other: &Self, tol: f64) -> bool {
+        self.zip_with(other)
+            .all(|(_, left, right)| (*left - *right).abs() <= tol)
+    }
+}
+
+/*
+The same mapping as IntervalMap, but owning its values instead of borrowing them. This
+frees the map from the lifetime of the value sources, at the cost of cloning values when
+segments get split.
+```
+omap: OwnedIntervalMap<i32, String> = OwnedIntervalMap::new(String::from("a"));
+omap.insert(10, 20, String::from("v"));
+omap.get(&15) == "v"
+```
+*/
+#[derive(Debug)]
+pub struct OwnedIntervalMap<K, V> {
+    m_map: BTreeMap<K, V>, // Keeping track of the mapping. Keys in order
+    default: V,            // The initial value, remembered for resetting regions
+}
+
+impl<K, V> OwnedIntervalMap<K, V>
+where
+    K: Eq + Hash + Copy + Ord + Min,
+    V: PartialEq + Clone,
+{
+    pub fn new(init_val: V) -> Self {
+        let mut m_map = BTreeMap::new();
+        m_map.insert(K::minimum(), init_val.clone());
+        OwnedIntervalMap {
+            m_map,
+            default: init_val,
+        }
+    }
+
+    pub fn insert(&mut self, begin_key: K, end_key: K, val: V) {
+        if begin_key >= end_key {
+            return;
+        }
+
+        let end_val = self.get(&end_key).clone();
+        let needs_end = end_val != val;
+        self.m_map
+            .retain(|key, _| *key < begin_key || *key > end_key);
+
+        // Continuing the segment left of begin_key needs no boundary of its own
+        match self.m_map.range(..begin_key).next_back() {
+            Some((_, left_val)) if *left_val == val => {}
+            _ => {
+                self.m_map.insert(begin_key, val);
+            }
+        }
+        if needs_end {
+            self.m_map.insert(end_key, end_val);
+        }
+    }
+
+    // Resets [begin_key, end_key) back to the default value
+    pub fn remove(&mut self, begin_key: K, end_key: K) {
+        self.insert(begin_key, end_key, self.default.clone());
+    }
+
+    // Always returns something
+    pub fn get(&self, key: &K) -> &V {
+        self.m_map
+            .range(..=*key)
+            .next_back()
+            .expect(ANCHOR_MISSING)
+            .1
+    }
+
+    // Adds delta to every value, the default included
+    pub fn offset_values(&mut self, delta: V)
+    where
+        V: Add<Output = V> + Copy,
+    {
+        for val in self.m_map.values_mut() {
+            *val = *val + delta;
+        }
+        self.default = self.default + delta;
+        self.coalesce();
+    }
+
+    // Removes every boundary that does not change the value, keeping the map canonical
+    fn coalesce(&mut self) {
+        let mut prev: Option<V> = None;
+        self.m_map.retain(|_, val| {
+            let keep = prev.as_ref() != Some(val);
+            prev = Some(val.clone());
+            keep
+        });
+    }
+}
+
+/*
+A read-only snapshot of an IntervalMap, made with IntervalMap::freeze. The boundaries are
+kept in a flat sorted array, so lookups are a plain binary search without allocating.
+*/
+#[derive(Debug)]
+pub struct FrozenIntervalMap<'a, K, V> {
+    entries: Vec<(K, &'a V)>, // Every boundary with its value, ascending. Starts at the anchor
+}
+
+impl<'a, K, V> FrozenIntervalMap<'a, K, V>
+where
+    K: Eq + Hash + Copy + Ord + Min,
+{
+    // Always returns something
+    pub fn get(&self, key: &K) -> &'a V {
+        let idx = self.entries.partition_point(|(start, _)| start <= key);
+        self.entries[idx.checked_sub(1).expect(ANCHOR_MISSING)].1
+    }
+}
+
+// Maps holding at most this many boundaries keep them in a sorted Vec
+const SMALL_LIMIT: usize = 16;
+
+/*
+The boundary storage behind IntervalMap. Small maps keep their boundaries in a sorted Vec,
+which saves the node allocations and pointer chasing of a BTreeMap. A Vec growing past
+SMALL_LIMIT entries turns into a BTreeMap, unless it is pinned, as done by
+IntervalMap::new_small. Either way it offers the part of the BTreeMap API the map uses.
+*/
+enum SegmentStore<K, V> {
+    Small { entries: Vec<(K, V)>, pinned: bool }, // Sorted by key, no key repeated
+    Large(BTreeMap<K, V>),
+}
+
+impl<K: Ord, V> SegmentStore<K, V> {
+    fn new() -> Self {
+        SegmentStore::Small {
+            entries: Vec::new(),
+            pinned: false,
+        }
+    }
+
+    fn pinned() -> Self {
+        SegmentStore::Small {
+            entries: Vec::new(),
+            pinned: true,
+        }
+    }
+
+    // Starts out in whichever backing suits the expected number of boundaries
+    fn with_capacity_hint(hint: usize) -> Self {
+        if hint > SMALL_LIMIT {
+            SegmentStore::Large(BTreeMap::new())
+        } else {
+            SegmentStore::Small {
+                entries: Vec::with_capacity(hint),
+                pinned: false,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn is_small(&self) -> bool {
+        matches!(self, SegmentStore::Small { .. })
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        match self {
+            SegmentStore::Small { entries, .. } => entries.len(),
+            SegmentStore::Large(map) => map.len(),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        match self {
+            SegmentStore::Small { entries, .. } => entries
+                .binary_search_by(|(probe, _)| probe.cmp(key))
+                .ok()
+                .map(|idx| &entries[idx].1),
+            SegmentStore::Large(map) => map.get(key),
+        }
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let old = match self {
+            SegmentStore::Small { entries, .. } => {
+                match entries.binary_search_by(|(probe, _)| probe.cmp(&key)) {
+                    Ok(idx) => Some(std::mem::replace(&mut entries[idx].1, val)),
+                    Err(idx) => {
+                        entries.insert(idx, (key, val));
+                        None
+                    }
+                }
+            }
+            SegmentStore::Large(map) => map.insert(key, val),
+        };
+        self.promote_if_full();
+        old
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        match self {
+            SegmentStore::Small { entries, .. } => entries
+                .binary_search_by(|(probe, _)| probe.cmp(key))
+                .ok()
+                .map(|idx| entries.remove(idx).1),
+            SegmentStore::Large(map) => map.remove(key),
+        }
+    }
+
+    // Empties the store. An unpinned store goes back to the Vec backing
+    fn clear(&mut self) {
+        match self {
+            SegmentStore::Small { entries, .. } => entries.clear(),
+            SegmentStore::Large(_) => *self = SegmentStore::new(),
+        }
+    }
+
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        match self {
+            SegmentStore::Small { entries, .. } => entries.retain_mut(|(key, val)| f(key, val)),
+            SegmentStore::Large(map) => map.retain(f),
+        }
+    }
+
+    // Removes every entry from key on and returns them in a store of the same kind
+    fn split_off(&mut self, key: &K) -> Self {
+        match self {
+            SegmentStore::Small { entries, pinned } => {
+                let idx = entries.partition_point(|(probe, _)| probe < key);
+                SegmentStore::Small {
+                    entries: entries.split_off(idx),
+                    pinned: *pinned,
+                }
+            }
+            SegmentStore::Large(map) => SegmentStore::Large(map.split_off(key)),
+        }
+    }
+
+    fn iter(&self) -> StoreIter<'_, K, V> {
+        self.range(..)
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> StoreIter<'_, K, V> {
+        match self {
+            SegmentStore::Small { entries, .. } => {
+                let lo = match range.start_bound() {
+                    Included(start) => entries.partition_point(|(key, _)| key < start),
+                    Excluded(start) => entries.partition_point(|(key, _)| key <= start),
+                    Unbounded => 0,
+                };
+                let hi = match range.end_bound() {
+                    Included(end) => entries.partition_point(|(key, _)| key <= end),
+                    Excluded(end) => entries.partition_point(|(key, _)| key < end),
+                    Unbounded => entries.len(),
+                };
+                StoreIter::Small(entries[lo..hi.max(lo)].iter())
+            }
+            SegmentStore::Large(map) => StoreIter::Large(map.range(range)),
+        }
+    }
+
+    fn iter_mut(&mut self) -> StoreIterMut<'_, K, V> {
+        match self {
+            SegmentStore::Small { entries, .. } => StoreIterMut::Small(entries.iter_mut()),
+            SegmentStore::Large(map) => StoreIterMut::Large(map.iter_mut()),
+        }
+    }
+
+    fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    fn values(&self) -> impl DoubleEndedIterator<Item = &V> + '_ {
+        self.iter().map(|(_, val)| val)
+    }
+
+    fn values_mut(&mut self) -> impl Iterator<Item = &mut V> + '_ {
+        self.iter_mut().map(|(_, val)| val)
+    }
+
+    // Moves the entries into a BTreeMap once an unpinned Vec has grown past SMALL_LIMIT
+    fn promote_if_full(&mut self) {
+        if let SegmentStore::Small {
+            entries,
+            pinned: false,
+        } = self
+        {
+            if entries.len() > SMALL_LIMIT {
+                let map = std::mem::take(entries).into_iter().collect();
+                *self = SegmentStore::Large(map);
+            }
+        }
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for SegmentStore<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.insert(key, val);
+        }
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for SegmentStore<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut store = SegmentStore::new();
+        store.extend(iter);
+        store
+    }
+}
+
+impl<K, V> IntoIterator for SegmentStore<K, V> {
+    type Item = (K, V);
+    type IntoIter = StoreIntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            SegmentStore::Small { entries, .. } => StoreIntoIter::Small(entries.into_iter()),
+            SegmentStore::Large(map) => StoreIntoIter::Large(map.into_iter()),
+        }
+    }
+}
+
+// Two stores are equal when they hold the same entries, whatever their backing
+impl<K: Ord, V: PartialEq> PartialEq for SegmentStore<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for SegmentStore<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SegmentStore::Small { entries, .. } => f
+                .debug_map()
+                .entries(entries.iter().map(|(key, val)| (key, val)))
+                .finish(),
+            SegmentStore::Large(map) => map.fmt(f),
+        }
+    }
+}
+
+enum StoreIter<'s, K, V> {
+    Small(std::slice::Iter<'s, (K, V)>),
+    Large(std::collections::btree_map::Range<'s, K, V>),
+}
+
+impl<'s, K, V> Iterator for StoreIter<'s, K, V> {
+    type Item = (&'s K, &'s V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            StoreIter::Small(iter) => iter.next().map(|(key, val)| (key, val)),
+            StoreIter::Large(iter) => iter.next(),
+        }
+    }
+}
+
+impl<K, V> DoubleEndedIterator for StoreIter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            StoreIter::Small(iter) => iter.next_back().map(|(key, val)| (key, val)),
+            StoreIter::Large(iter) => iter.next_back(),
+        }
+    }
+}
+
+enum StoreIterMut<'s, K, V> {
+    Small(std::slice::IterMut<'s, (K, V)>),
+    Large(std::collections::btree_map::IterMut<'s, K, V>),
+}
+
+impl<'s, K, V> Iterator for StoreIterMut<'s, K, V> {
+    type Item = (&'s K, &'s mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            StoreIterMut::Small(iter) => iter.next().map(|(key, val)| (&*key, val)),
+            StoreIterMut::Large(iter) => iter.next(),
+        }
+    }
+}
+
+enum StoreIntoIter<K, V> {
+    Small(std::vec::IntoIter<(K, V)>),
+    Large(std::collections::btree_map::IntoIter<K, V>),
+}
+
+impl<K, V> Iterator for StoreIntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            StoreIntoIter::Small(iter) => iter.next(),
+            StoreIntoIter::Large(iter) => iter.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn it_works() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert_eq!(test_map.get(&10), &'a');
+
+        test_map.insert(10, 20, &'b');
+        assert_eq!(test_map.get(&11), &'b');
+        assert_eq!(test_map.get(&20), &'a');
+
+        test_map.insert(25, 30, &'c');
+        assert_eq!(test_map.get(&10), &'b');
+        assert_eq!(test_map.get(&20), &'a');
+        assert_eq!(test_map.get(&26), &'c');
+        assert_eq!(test_map.get(&30), &'a');
+    }
+
+    #[test]
+    fn overlapping() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+
+        assert_eq!(test_map.get(&12), &'b');
+        assert_eq!(test_map.get(&15), &'c');
+        assert_eq!(test_map.get(&20), &'c');
+        assert_eq!(test_map.get(&25), &'a');
+    }
+
+    #[test]
+    fn intertwined() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+
+        assert_eq!(test_map.get(&10), &'b');
+        assert_eq!(test_map.get(&20), &'c');
+        assert_eq!(test_map.get(&25), &'c');
+        assert_eq!(test_map.get(&30), &'a');
+    }
+
+    #[test]
+    fn multiple_overlapping() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(20, 25, &'b');
+        test_map.insert(10, 25, &'c');
+        test_map.insert(22, 50, &'c');
+        assert_eq!(test_map.get(&5), &'a');
+        assert_eq!(test_map.get(&10), &'c');
+        assert_eq!(test_map.get(&20), &'c');
+        assert_eq!(test_map.get(&51), &'a');
+        assert_eq!(test_map.debug_dump(), "[-inf,10)=a;[10,50)=c;[50,inf)=a");
+    }
+
+    #[test]
+    fn merge_into_matches_merge() {
+        let max = |a: &'static char, b: &'static char| if a > b { a } else { b };
+
+        let mut first: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        first.insert(10, 20, &'c');
+        let mut second: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        second.insert(15, 30, &'b');
+        let mut third: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        third.insert(5, 12, &'d');
+        third.insert(25, 40, &'c');
+
+        let merged = first.merge(&second, max).merge(&third, max);
+        first.merge_into(&second, max);
+        first.merge_into(&third, max);
+
+        assert_eq!(first.m_map, merged.m_map);
+        assert_eq!(first.default, merged.default);
+        assert_eq!(first.get(&4), &'a');
+        assert_eq!(first.get(&5), &'d');
+        assert_eq!(first.get(&12), &'c');
+        assert_eq!(first.get(&20), &'b');
+        assert_eq!(first.get(&25), &'c');
+        assert_eq!(first.get(&40), &'a');
+
+        // The merged default is what removals reset to
+        let mut low: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        low.insert(10, 20, &'b');
+        let high: IntervalMap<i32, char> = IntervalMap::new(&'z');
+        let mut merged = low.merge(&high, max);
+        low.merge_into(&high, max);
+        assert_eq!(low.default, merged.default);
+        low.remove(10, 20);
+        merged.remove(10, 20);
+        assert_eq!(low.get(&15), &'z');
+        assert_eq!(low.m_map, merged.m_map);
+    }
+
+    #[test]
+    fn events_roundtrip() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+
+        let events = test_map.to_events();
+        assert_eq!(
+            events,
+            vec![(i32::MIN, &'a'), (10, &'b'), (15, &'c'), (25, &'a')]
+        );
+
+        let rebuilt = IntervalMap::from_events(events);
+        assert_eq!(rebuilt.m_map, test_map.m_map);
+    }
+
+    #[test]
+    #[should_panic(expected = "strictly ascending events")]
+    fn from_events_out_of_order() {
+        let _: IntervalMap<i32, char> =
+            IntervalMap::from_events(vec![(i32::MIN, &'a'), (20, &'c'), (10, &'b')]);
+    }
+
+    #[test]
+    #[should_panic(expected = "first event at K::minimum")]
+    fn from_events_without_anchor() {
+        let _: IntervalMap<i32, char> = IntervalMap::from_events(vec![(10, &'b'), (20, &'c')]);
+    }
+
+    #[test]
+    fn partition_point() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&1);
+        test_map.insert(10, 20, &3);
+        test_map.insert(20, 30, &5);
+        test_map.insert(30, 40, &8);
+
+        assert_eq!(test_map.partition_point(|val| *val < 5), Some(20));
+        assert_eq!(test_map.partition_point(|val| *val < 100), None);
+        assert_eq!(test_map.partition_point(|val| *val > 100), None);
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<IntervalMap<i32, char>>();
+
+        let mut test_map: IntervalMap<'static, i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        let shared = std::sync::Arc::new(test_map);
+
+        let reader = std::sync::Arc::clone(&shared);
+        let handle = std::thread::spawn(move || (*reader.get(&15), *reader.get(&20)));
+        assert_eq!(handle.join().unwrap(), ('b', 'a'));
+        assert_eq!(shared.get(&15), &'b');
+    }
+
+    #[test]
+    fn run_lengths_in() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+
+        assert_eq!(
+            test_map.run_lengths_in(15, 35),
+            vec![(5, &'b'), (10, &'c'), (5, &'a')]
+        );
+        assert_eq!(test_map.run_lengths_in(12, 18), vec![(6, &'b')]);
+        assert!(test_map.run_lengths_in(18, 18).is_empty());
+    }
+
+    #[test]
+    fn swap_ranges() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(30, 40, &'c');
+
+        test_map.swap_ranges((10, 20), (30, 40));
+        assert_eq!(test_map.get(&9), &'a');
+        assert_eq!(test_map.get(&10), &'c');
+        assert_eq!(test_map.get(&19), &'c');
+        assert_eq!(test_map.get(&20), &'a');
+        assert_eq!(test_map.get(&30), &'b');
+        assert_eq!(test_map.get(&39), &'b');
+        assert_eq!(test_map.get(&40), &'a');
+    }
+
+    #[test]
+    fn fill_gaps() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(25, 30, &'c');
+        test_map.insert(35, 40, &'b');
+
+        test_map.fill_gaps(&'b');
+        assert_eq!(test_map.get(&0), &'b');
+        assert_eq!(test_map.get(&22), &'b');
+        assert_eq!(test_map.get(&27), &'c');
+        assert_eq!(test_map.get(&32), &'b');
+        assert_eq!(test_map.get(&50), &'b');
+        // Both gaps merged with their equal neighbours, leaving only the 'c' segment
+        assert_eq!(
+            test_map.to_events(),
+            vec![(i32::MIN, &'b'), (25, &'c'), (30, &'b')]
+        );
+    }
+
+    #[test]
+    fn with_default_fn() {
+        let calls = std::cell::Cell::new(0);
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_default_fn(|| {
+            calls.set(calls.get() + 1);
+            &'a'
+        });
+        test_map.insert(10, 20, &'b');
+
+        assert_eq!(test_map.get(&5), &'a');
+        assert_eq!(test_map.get(&15), &'b');
+        assert_eq!(test_map.get(&25), &'a');
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn transitions_in() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'d');
+
+        assert_eq!(
+            test_map.transitions().collect::<Vec<_>>(),
+            vec![
+                (10, &'a', &'b'),
+                (20, &'b', &'c'),
+                (30, &'c', &'a'),
+                (40, &'a', &'d'),
+                (50, &'d', &'a')
+            ]
+        );
+        // Boundaries at the window edges are not strictly inside it
+        assert_eq!(
+            test_map.transitions_in(10, 40).collect::<Vec<_>>(),
+            vec![(20, &'b', &'c'), (30, &'c', &'a')]
+        );
+        assert_eq!(test_map.transitions_in(21, 29).count(), 0);
+        assert_eq!(test_map.transitions_in(40, 40).count(), 0);
+    }
+
+    #[test]
+    fn anchor_survives_mutations() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(i32::MIN, 10, &'b');
+        assert_eq!(test_map.get(&i32::MIN), &'b');
+        test_map.insert(5, 20, &'c');
+        test_map.remove(i32::MIN, 8);
+        assert_eq!(test_map.get(&i32::MIN), &'a');
+        assert_eq!(test_map.get(&8), &'c');
+        test_map.remove(i32::MIN, 30);
+        assert_eq!(test_map.get(&i32::MIN), &'a');
+        assert_eq!(test_map.m_map.len(), 1);
+
+        test_map.insert(i32::MIN, i32::MAX, &'d');
+        test_map.clear();
+        assert_eq!(test_map.get(&i32::MIN), &'a');
+        assert_eq!(test_map.get(&0), &'a');
+        test_map.check_invariants();
+    }
+
+    #[test]
+    fn max_min_segment() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&5);
+        test_map.insert(10, 20, &9);
+        test_map.insert(30, 40, &1);
+        test_map.insert(50, 60, &9);
+
+        assert_eq!(test_map.max_segment(), (10, Some(20), &9));
+        assert_eq!(test_map.min_segment(), (30, Some(40), &1));
+    }
+
+    #[test]
+    fn value_before_after() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+
+        assert_eq!(test_map.value_before(&19), &'a');
+        assert_eq!(test_map.value_after(&19), &'c');
+        assert_eq!(test_map.value_before(&20), &'b');
+        assert_eq!(test_map.value_after(&20), &'a');
+
+        // Nothing beyond the outermost segments
+        assert_eq!(test_map.value_before(&0), &'a');
+        assert_eq!(test_map.value_after(&40), &'a');
+        assert_eq!(test_map.value_after(&0), &'b');
+    }
+
+    #[test]
+    fn domain_and_default_length() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_bounds(0, 100, &'a');
+        assert_eq!(test_map.domain_length(), 100);
+        assert_eq!(test_map.default_length(), 100);
+
+        test_map.insert(10, 30, &'b');
+        test_map.insert(90, 120, &'c');
+        assert_eq!(test_map.domain_length(), 100);
+        assert_eq!(test_map.default_length(), 70);
+    }
+
+    #[test]
+    #[should_panic]
+    fn domain_length_unbounded() {
+        let test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.domain_length();
+    }
+
+    #[test]
+    fn fold_segments() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        test_map.insert(10, 20, &3);
+        test_map.insert(20, 25, &4);
+        test_map.insert(30, 40, &1);
+
+        let weighted = test_map.fold_segments(0, |acc, start, end, val| match end {
+            Some(end) if *val != 0 => acc + (end - start) * val,
+            _ => acc,
+        });
+        assert_eq!(weighted, 10 * 3 + 5 * 4 + 10);
+
+        let count = test_map.fold_segments(0, |acc, _, _, _| acc + 1);
+        assert_eq!(count, 6);
+    }
+
+    #[test]
+    fn segment_windows() {
+        let test_map: IntervalMap<i32, char> =
+            IntervalMap::from_events(vec![(i32::MIN, &'a'), (10, &'b'), (20, &'c')]);
+
+        let pairs = test_map.segment_windows().collect::<Vec<_>>();
+        assert_eq!(
+            pairs,
+            vec![
+                ((i32::MIN, Some(10), &'a'), (10, Some(20), &'b')),
+                ((10, Some(20), &'b'), (20, None, &'c')),
+            ]
+        );
+        assert!(pairs
+            .iter()
+            .any(|(left, right)| left.2 == &'b' && right.2 == &'c'));
+    }
+
+    #[test]
+    fn no_redundant_boundaries() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'a');
+        assert_eq!(test_map.m_map.len(), 1);
+
+        test_map.insert(10, 20, &'b');
+        test_map.insert(10, 20, &'a');
+        assert_eq!(test_map.m_map.len(), 1);
+
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(20, 30, &'b');
+        assert_eq!(test_map.to_events().len(), test_map.m_map.len());
+        assert_eq!(test_map.m_map.len(), 3);
+    }
+
+    #[test]
+    fn value_percentile() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::with_bounds(0, 100, &5);
+        test_map.insert(0, 20, &1);
+        test_map.insert(20, 70, &3);
+
+        assert_eq!(test_map.value_percentile(0.5), &3);
+        assert_eq!(test_map.value_percentile(0.1), &1);
+        assert_eq!(test_map.value_percentile(0.9), &5);
+        assert_eq!(test_map.value_percentile(-1.0), &1);
+        assert_eq!(test_map.value_percentile(2.0), &5);
+    }
+
+    #[test]
+    fn move_range() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 15, &'b');
+        test_map.insert(15, 20, &'c');
+
+        test_map.move_range((10, 20), 14);
+        assert_eq!(test_map.get(&10), &'a');
+        assert_eq!(test_map.get(&13), &'a');
+        assert_eq!(test_map.get(&14), &'b');
+        assert_eq!(test_map.get(&18), &'b');
+        assert_eq!(test_map.get(&19), &'c');
+        assert_eq!(test_map.get(&23), &'c');
+        assert_eq!(test_map.get(&24), &'a');
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn well_formed() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+        test_map.remove(12, 18);
+        test_map.assert_well_formed();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "does not change the value")]
+    fn well_formed_redundant_boundary() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.raw_map_mut().insert(15, &'b');
+        test_map.assert_well_formed();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "anchor at K::minimum must exist")]
+    fn well_formed_missing_anchor() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.raw_map_mut().remove(&i32::MIN);
+        test_map.assert_well_formed();
+    }
+
+    #[test]
+    fn insert_blend() {
+        static LEVELS: [i32; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+        let add = |old: &i32, extra: &i32| &LEVELS[(old + extra) as usize];
+
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&LEVELS[0]);
+        test_map.insert(10, 20, &LEVELS[2]);
+        test_map.insert_blend(15, 30, &LEVELS[3], add);
+
+        assert_eq!(test_map.get(&12), &2);
+        assert_eq!(test_map.get(&15), &5);
+        assert_eq!(test_map.get(&20), &3);
+        assert_eq!(test_map.get(&29), &3);
+        assert_eq!(test_map.get(&30), &0);
+    }
+
+    #[test]
+    fn to_vec() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(25, 30, &'c');
+
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'a'),
+                (10, &'b'),
+                (20, &'a'),
+                (25, &'c'),
+                (30, &'a')
+            ]
+        );
+    }
+
+    #[test]
+    fn distinct_value_count() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert_eq!(test_map.distinct_value_count(), 1);
+
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+        test_map.insert(30, 40, &'b');
+        test_map.insert(35, 45, &'d');
+        test_map.insert(40, 50, &'c');
+        assert_eq!(test_map.distinct_value_count(), 4);
+
+        test_map.insert(0, 100, &'b');
+        assert_eq!(test_map.distinct_value_count(), 2);
+    }
+
+    #[test]
+    fn context() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        let ctx = test_map.context(&15);
+        assert_eq!(ctx.value, &'b');
+        assert_eq!(ctx.start, 10);
+        assert_eq!(ctx.end, Some(20));
+
+        assert_eq!(
+            test_map.context(&25),
+            Context {
+                value: &'a',
+                start: 20,
+                end: None
+            }
+        );
+    }
+
+    #[test]
+    fn truncate_before() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(30, 40, &'c');
+
+        test_map.truncate_before(15);
+        assert_eq!(test_map.get(&10), &'a');
+        assert_eq!(test_map.get(&14), &'a');
+        assert_eq!(test_map.get(&15), &'b');
+        assert_eq!(test_map.get(&20), &'a');
+        assert_eq!(test_map.get(&35), &'c');
+    }
+
+    #[test]
+    fn truncate_after() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(30, 40, &'c');
+
+        test_map.truncate_after(15);
+        assert_eq!(test_map.get(&9), &'a');
+        assert_eq!(test_map.get(&10), &'b');
+        assert_eq!(test_map.get(&14), &'b');
+        assert_eq!(test_map.get(&15), &'a');
+        assert_eq!(test_map.get(&35), &'a');
+
+        test_map.truncate_after(i32::MIN);
+        assert_eq!(test_map.get(&12), &'a');
+    }
+
+    #[test]
+    fn try_from_ranges() {
+        let ranges = vec![(20, 30, &'c'), (10, 20, &'b'), (40, 50, &'d')];
+        let test_map: IntervalMap<i32, char> =
+            IntervalMap::try_from_ranges(&'a', ranges.into_iter()).unwrap();
+        assert_eq!(test_map.get(&5), &'a');
+        assert_eq!(test_map.get(&10), &'b');
+        assert_eq!(test_map.get(&20), &'c');
+        assert_eq!(test_map.get(&35), &'a');
+        assert_eq!(test_map.get(&45), &'d');
+
+        let overlapping = vec![(10, 20, &'b'), (40, 50, &'d'), (15, 25, &'c')];
+        let result: Result<IntervalMap<i32, char>, _> =
+            IntervalMap::try_from_ranges(&'a', overlapping.into_iter());
+        assert_eq!(result.unwrap_err(), IntervalError::Overlap);
+    }
+
+    #[test]
+    fn prefix_sum() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::with_bounds(0, 100, &1);
+        test_map.insert(10, 20, &3);
+        test_map.insert(20, 40, &0);
+
+        assert_eq!(test_map.prefix_sum(0), 0);
+        assert_eq!(test_map.prefix_sum(10), 10);
+        assert_eq!(test_map.prefix_sum(15), 10 + 15);
+        assert_eq!(test_map.prefix_sum(50), 10 + 30 + 10);
+    }
+
+    #[test]
+    fn nearest_with_value() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 40, &'c');
+        test_map.insert(50, 60, &'b');
+        test_map.insert(60, 70, &'c');
+
+        // Behind the query point
+        assert_eq!(test_map.nearest_with_value(&25, &'b'), Some((10, Some(20))));
+        // Ahead of the query point
+        assert_eq!(test_map.nearest_with_value(&38, &'b'), Some((50, Some(60))));
+        // Equally far, the earlier one wins
+        assert_eq!(test_map.nearest_with_value(&35, &'b'), Some((10, Some(20))));
+        assert_eq!(test_map.nearest_with_value(&55, &'b'), Some((50, Some(60))));
+        assert_eq!(test_map.nearest_with_value(&55, &'d'), None);
+    }
+
+    #[test]
+    fn dilate() {
+        let mut test_map: IntervalMap<i32, bool> = IntervalMap::new(&false);
+        test_map.insert(10, 20, &true);
+        test_map.insert(25, 40, &true);
+
+        test_map.dilate(3);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![(i32::MIN, &false), (7, &true), (43, &false)]
+        );
+
+        test_map.insert(i32::MIN, 0, &true);
+        test_map.dilate(1);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![(i32::MIN, &true), (1, &false), (6, &true), (44, &false)]
+        );
+    }
+
+    #[test]
+    fn dilate_at_key_range_ends() {
+        let mut test_map: IntervalMap<i32, bool> = IntervalMap::new(&false);
+        test_map.insert(i32::MIN + 2, 0, &true);
+        test_map.insert(20, i32::MAX, &true);
+
+        test_map.dilate(5);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![(i32::MIN, &true), (5, &false), (15, &true)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative radius")]
+    fn dilate_negative() {
+        let mut test_map: IntervalMap<i32, bool> = IntervalMap::new(&false);
+        test_map.insert(10, 20, &true);
+        test_map.dilate(-8);
+    }
+
+    #[test]
+    fn erode() {
+        let mut test_map: IntervalMap<i32, bool> = IntervalMap::new(&false);
+        test_map.insert(10, 20, &true);
+        test_map.insert(25, 40, &true);
+
+        test_map.erode(3);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &false),
+                (13, &true),
+                (17, &false),
+                (28, &true),
+                (37, &false)
+            ]
+        );
+
+        // The narrower segment erodes away entirely
+        test_map.erode(2);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![(i32::MIN, &false), (30, &true), (35, &false)]
+        );
+    }
+
+    #[test]
+    fn as_fn() {
+        fn sample<F: Fn(i32) -> &'static char>(f: F, keys: &[i32]) -> String {
+            keys.iter().map(|key| *f(*key)).collect()
+        }
+
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+
+        assert_eq!(sample(test_map.as_fn(), &[5, 10, 19, 20, 30]), "abbca");
+    }
+
+    #[test]
+    fn splice() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 40, &'b');
+
+        let mut replacement: IntervalMap<i32, char> = IntervalMap::new(&'b');
+        replacement.insert(0, 25, &'c');
+        replacement.insert(25, 100, &'d');
+
+        test_map.splice((20, 30), &replacement);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'a'),
+                (10, &'b'),
+                (20, &'c'),
+                (25, &'d'),
+                (30, &'b'),
+                (40, &'a')
+            ]
+        );
+    }
+
+    #[test]
+    fn splice_keeps_own_default() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'b');
+        test_map.insert(10, 40, &'c');
+
+        let mut replacement: IntervalMap<i32, char> = IntervalMap::new(&'z');
+        replacement.insert(15, 20, &'d');
+
+        test_map.splice((10, 30), &replacement);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'b'),
+                (15, &'d'),
+                (20, &'b'),
+                (30, &'c'),
+                (40, &'b')
+            ]
+        );
+    }
+
+    #[test]
+    fn points_in() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        assert_eq!(
+            test_map.points_in(17, 22).collect::<Vec<_>>(),
+            vec![(17, &'b'), (18, &'b'), (19, &'b'), (20, &'a'), (21, &'a')]
+        );
+        assert_eq!(test_map.points_in(5, 5).count(), 0);
+    }
+
+    #[test]
+    fn insert_changed() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert!(test_map.insert_changed(10, 20, &'b'));
+        assert!(!test_map.insert_changed(10, 20, &'b'));
+        assert!(!test_map.insert_changed(12, 18, &'b'));
+        assert!(test_map.insert_changed(15, 25, &'b'));
+        assert!(!test_map.insert_changed(30, 40, &'a'));
+        assert_eq!(test_map.get(&24), &'b');
+    }
+
+    #[test]
+    fn segments_matching() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'b');
+
+        assert_eq!(
+            test_map
+                .segments_matching(|val| *val == 'b')
+                .collect::<Vec<_>>(),
+            vec![(10, Some(20), &'b'), (40, Some(50), &'b')]
+        );
+        assert_eq!(test_map.segments_matching(|val| *val == 'z').count(), 0);
+    }
+
+    #[test]
+    fn extend_to() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(40, 50, &'c');
+
+        test_map.extend_to(30);
+        assert_eq!(test_map.get(&25), &'b');
+        assert_eq!(test_map.get(&30), &'b');
+        assert_eq!(test_map.get(&31), &'a');
+        assert_eq!(test_map.to_events().len(), 5);
+
+        // Inside a non-default segment, or without a segment before the gap
+        test_map.extend_to(45);
+        test_map.extend_to(5);
+        assert_eq!(test_map.get(&5), &'a');
+        assert_eq!(test_map.get(&50), &'a');
+
+        test_map.extend_to(i32::MAX);
+        assert_eq!(test_map.get(&50), &'c');
+        assert_eq!(test_map.get(&i32::MAX), &'c');
+    }
+
+    #[test]
+    fn runs() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+
+        let runs = test_map.runs();
+        assert_eq!(
+            runs,
+            vec![
+                (i32::MIN, Some(10), 0, &'a'),
+                (10, Some(20), 1, &'b'),
+                (20, Some(30), 2, &'c'),
+                (30, None, 3, &'a')
+            ]
+        );
+        for (idx, window) in runs.windows(2).enumerate() {
+            assert_eq!(window[0].2, idx);
+            assert_eq!(window[1].2, idx + 1);
+            assert_eq!(window[0].1, Some(window[1].0));
+        }
+    }
+
+    #[test]
+    fn covers_exactly() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(35, 40, &'b');
+
+        assert!(test_map.covers_exactly(10, 30));
+        assert!(test_map.covers_exactly(15, 25));
+        assert!(!test_map.covers_exactly(10, 40));
+        assert!(!test_map.covers_exactly(5, 15));
+    }
+
+    #[test]
+    fn rotate() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_bounds(0, 70, &'a');
+        test_map.insert(0, 10, &'b');
+        test_map.insert(50, 70, &'c');
+
+        test_map.rotate(15);
+        assert_eq!(test_map.get(&0), &'c');
+        assert_eq!(test_map.get(&14), &'c');
+        assert_eq!(test_map.get(&15), &'b');
+        assert_eq!(test_map.get(&24), &'b');
+        assert_eq!(test_map.get(&25), &'a');
+        assert_eq!(test_map.get(&64), &'a');
+        assert_eq!(test_map.get(&65), &'c');
+        assert_eq!(test_map.get(&69), &'c');
+        assert_eq!(test_map.get(&70), &'a');
+
+        // Rotating back restores the original content
+        test_map.rotate(-15);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'a'),
+                (0, &'b'),
+                (10, &'a'),
+                (50, &'c'),
+                (70, &'a')
+            ]
+        );
+    }
+
+    #[test]
+    fn rotate_near_key_range_end() {
+        let top = i32::MAX - 10;
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_bounds(top, i32::MAX, &'a');
+        test_map.insert(top + 2, top + 8, &'b');
+
+        test_map.rotate(5);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'a'),
+                (top, &'b'),
+                (top + 3, &'a'),
+                (top + 7, &'b'),
+                (i32::MAX, &'a')
+            ]
+        );
+    }
+
+    #[test]
+    fn approx_eq() {
+        let mut test_map: IntervalMap<i32, f64> = IntervalMap::new(&0.0);
+        test_map.insert(10, 20, &1.5);
+        test_map.insert(20, 30, &2.5);
+
+        // Cut differently, but holding about the same values
+        let mut close: IntervalMap<i32, f64> = IntervalMap::new(&0.0001);
+        close.insert(10, 15, &1.5001);
+        close.insert(15, 20, &1.4999);
+        close.insert(20, 30, &2.5);
+        assert!(test_map.approx_eq(&close, 0.001));
+        assert!(!test_map.approx_eq(&close, 0.00001));
+
+        let mut shifted: IntervalMap<i32, f64> = IntervalMap::new(&0.0);
+        shifted.insert(11, 20, &1.5);
+        shifted.insert(20, 30, &2.5);
+        assert!(!test_map.approx_eq(&shifted, 0.001));
+    }
+
+    #[test]
+    fn scale() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(-10, 20, &'b');
+        test_map.insert(25, 30, &'c');
+
+        test_map.scale(2);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![
+                (i32::MIN, &'a'),
+                (-20, &'b'),
+                (40, &'a'),
+                (50, &'c'),
+                (60, &'a')
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "scale overflowed the key range")]
+    fn scale_overflow() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(1 << 30, (1 << 30) + 1, &'b');
+        test_map.scale(4);
+    }
+
+    #[test]
+    fn scale_bounds() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_bounds(0, 100, &'a');
+        test_map.insert(10, 20, &'b');
+        test_map.scale(3);
+        assert_eq!(test_map.domain_length(), 300);
+        assert_eq!(test_map.default_length(), 270);
+    }
+
+    #[test]
+    #[should_panic(expected = "positive factor")]
+    fn scale_by_zero() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.scale(0);
+    }
+
+    #[test]
+    fn into_owned() {
+        let owned = {
+            let sources = [String::from("a"), String::from("b"), String::from("c")];
+            let mut test_map: IntervalMap<i32, String> = IntervalMap::new(&sources[0]);
+            test_map.insert(10, 20, &sources[1]);
+            test_map.insert(15, 25, &sources[2]);
+            test_map.into_owned()
+        };
+
+        assert_eq!(owned.get(&5), "a");
+        assert_eq!(owned.get(&10), "b");
+        assert_eq!(owned.get(&15), "c");
+        assert_eq!(owned.get(&25), "a");
+    }
+
+    #[test]
+    fn owned_insert() {
+        let mut owned: OwnedIntervalMap<i32, char> = OwnedIntervalMap::new('a');
+        owned.insert(10, 20, 'b');
+        owned.insert(15, 25, 'c');
+        owned.insert(i32::MIN, 12, 'd');
+        assert_eq!(owned.get(&0), &'d');
+        assert_eq!(owned.get(&12), &'b');
+        assert_eq!(owned.get(&15), &'c');
+        assert_eq!(owned.get(&25), &'a');
+
+        owned.remove(i32::MIN, 30);
+        assert_eq!(owned.get(&15), &'a');
+        assert_eq!(owned.m_map.len(), 1);
+    }
+
+    #[test]
+    fn first_gap_at_least() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(i32::MIN, 5, &'b');
+        test_map.insert(8, 12, &'c');
+        test_map.insert(20, 30, &'d');
+
+        assert_eq!(test_map.first_gap_at_least(2), Some((5, Some(8))));
+        assert_eq!(test_map.first_gap_at_least(5), Some((12, Some(20))));
+        assert_eq!(test_map.first_gap_at_least(100), Some((30, None)));
+
+        let free: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert_eq!(free.first_gap_at_least(100), Some((i32::MIN, None)));
+
+        let mut anchored: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        anchored.insert(0, 10, &'b');
+        anchored.insert(12, 20, &'b');
+        assert_eq!(anchored.first_gap_at_least(2), Some((10, Some(12))));
+        assert_eq!(anchored.first_gap_at_least(5), Some((20, None)));
+    }
+
+    #[test]
+    fn remove_changed() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        assert!(!test_map.remove_changed(25, 40));
+        assert!(test_map.remove_changed(15, 25));
+        assert!(!test_map.remove_changed(15, 25));
+        assert_eq!(test_map.get(&14), &'b');
+        assert_eq!(test_map.get(&15), &'a');
+    }
+
+    #[test]
+    fn integrate() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        test_map.insert(0, 60, &2);
+        test_map.insert(60, 120, &5);
+
+        assert_eq!(test_map.integrate(30, 90), 30 * 2 + 30 * 5);
+        assert_eq!(test_map.integrate(-10, 10), 10 * 2);
+        assert_eq!(test_map.integrate(90, 90), 0);
+    }
+
+    #[test]
+    fn closest_boundary() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert_eq!(test_map.closest_boundary(&10), None);
+
+        test_map.insert(10, 20, &'b');
+        assert_eq!(test_map.closest_boundary(&15), Some(10));
+        assert_eq!(test_map.closest_boundary(&16), Some(20));
+        assert_eq!(test_map.closest_boundary(&14), Some(10));
+        assert_eq!(test_map.closest_boundary(&20), Some(20));
+        assert_eq!(test_map.closest_boundary(&i32::MIN), Some(10));
+        assert_eq!(test_map.closest_boundary(&100), Some(20));
+
+        // Distances across most of the key range do not overflow
+        let mut wide: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        wide.insert(-2_000_000_000, 2_000_000_000, &'b');
+        assert_eq!(wide.closest_boundary(&1_999_999_000), Some(2_000_000_000));
+        assert_eq!(wide.closest_boundary(&-1_999_999_000), Some(-2_000_000_000));
+        assert_eq!(wide.closest_boundary(&0), Some(-2_000_000_000));
+    }
+
+    #[test]
+    fn overlay_by_priority() {
+        // 0 = ok, 1 = warning, 2 = critical
+        let mut first: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        first.insert(10, 30, &1);
+        let mut second: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        second.insert(20, 40, &2);
+        second.insert(50, 60, &1);
+
+        let overlay = first.overlay_by_priority(&second);
+        assert_eq!(
+            overlay.to_vec(),
+            vec![
+                (i32::MIN, &0),
+                (10, &1),
+                (20, &2),
+                (40, &0),
+                (50, &1),
+                (60, &0)
+            ]
+        );
+    }
+
+    #[test]
+    fn transition_count() {
+        let mut test_map: IntervalMap<i32, char> =
+            IntervalMap::from_events(vec![(i32::MIN, &'a'), (10, &'b'), (20, &'c')]);
+        assert_eq!(test_map.transition_count(), 2);
+
+        // Extending a segment with its own value adds no transitions
+        test_map.insert(15, 25, &'c');
+        test_map.insert(5, 10, &'b');
+        assert_eq!(test_map.transition_count(), 2);
+    }
+
+    #[test]
+    fn fill_forward() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::with_bounds(0, 100, &'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(40, 60, &'c');
+
+        test_map.fill_forward();
+        assert_eq!(test_map.get(&30), &'a');
+        assert_eq!(test_map.get(&60), &'c');
+        assert_eq!(test_map.get(&99), &'c');
+        assert_eq!(test_map.get(&100), &'a');
+        assert_eq!(test_map.transition_count(), 4);
+
+        // Nothing assigned before the tail, so nothing to hold
+        let mut empty: IntervalMap<i32, char> = IntervalMap::with_bounds(0, 100, &'a');
+        empty.fill_forward();
+        assert_eq!(empty.to_vec(), vec![(i32::MIN, &'a')]);
+    }
+
+    #[test]
+    fn conflicts() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new_logging(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+        // Same value and default regions are no conflicts
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'d');
+
+        assert_eq!(test_map.conflicts(), &[(15, 20, &'b', &'c')]);
+
+        let mut quiet: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        quiet.insert(10, 20, &'b');
+        quiet.insert(15, 25, &'c');
+        assert!(quiet.conflicts().is_empty());
+    }
+
+    #[test]
+    fn iter_intervals_to() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        assert_eq!(
+            test_map.iter_intervals_to(100).collect::<Vec<_>>(),
+            vec![(i32::MIN, 10, &'a'), (10, 20, &'b'), (20, 100, &'a')]
+        );
+        assert_eq!(
+            test_map.iter_intervals_to(15).collect::<Vec<_>>(),
+            vec![(i32::MIN, 10, &'a'), (10, 15, &'b')]
+        );
+        assert_eq!(
+            test_map.iter_intervals_to(5).collect::<Vec<_>>(),
+            vec![(i32::MIN, 5, &'a')]
+        );
+    }
+
+    #[test]
+    fn group_by() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        test_map.insert(10, 20, &2);
+        test_map.insert(20, 30, &3);
+        test_map.insert(30, 40, &5);
+        test_map.insert(40, 50, &4);
+
+        let parity = |val: &i32| val % 2 == 0;
+        assert_eq!(
+            test_map.group_by(parity).collect::<Vec<_>>(),
+            vec![
+                (i32::MIN, Some(20), true),
+                (20, Some(40), false),
+                (40, None, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn iter_bounded() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        let segments = test_map.iter_bounded().collect::<Vec<_>>();
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0], (i32::MIN, 10, &'a'));
+        assert_eq!(segments.last(), Some(&(20, i32::MAX, &'a')));
+
+        test_map.insert(30, i32::MAX, &'c');
+        assert_eq!(test_map.iter_bounded().last(), Some((30, i32::MAX, &'c')));
+        assert!(test_map.iter_bounded().all(|(start, end, _)| start < end));
+    }
+
+    #[test]
+    fn assigned_extent() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        assert_eq!(test_map.assigned_extent(), None);
+
+        test_map.insert(40, 50, &'c');
+        assert_eq!(test_map.assigned_extent(), Some((40, 50)));
+        test_map.insert(10, 20, &'b');
+        test_map.insert(70, 75, &'b');
+        assert_eq!(test_map.assigned_extent(), Some((10, 75)));
+
+        test_map.remove(0, 100);
+        assert_eq!(test_map.assigned_extent(), None);
+    }
+
+    #[test]
+    fn ranges_by_value() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'b');
+        test_map.insert(50, 60, &'c');
+
+        let ranges = test_map.ranges_by_value();
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[&'b'], vec![(10, Some(20)), (40, Some(50))]);
+        assert_eq!(ranges[&'c'], vec![(20, Some(30)), (50, Some(60))]);
+        assert_eq!(
+            ranges[&'a'],
+            vec![(i32::MIN, Some(10)), (30, Some(40)), (60, None)]
+        );
+    }
+
+    #[test]
+    fn raw_entry() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+
+        assert_eq!(test_map.raw_entry(&15), (&10, &'b'));
+        assert_eq!(test_map.raw_entry(&10), (&10, &'b'));
+        assert_eq!(test_map.raw_entry(&25), (&20, &'a'));
+        assert_eq!(test_map.raw_entry(&0), (&i32::MIN, &'a'));
+    }
+
+    #[test]
+    fn insert_width() {
+        let mut by_width: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        by_width.insert_width(10, 10, &'b');
+        let mut by_end: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        by_end.insert(10, 20, &'b');
+        assert_eq!(by_width.m_map, by_end.m_map);
+
+        by_width.insert_width(30, 0, &'c');
+        by_width.insert_width(30, -5, &'c');
+        assert_eq!(by_width.m_map, by_end.m_map);
+    }
+
+    #[test]
+    fn intern_values() {
+        let (first_b, second_b) = (String::from("b"), String::from("b"));
+        let default = String::from("a");
+        let mut test_map: IntervalMap<i32, String> = IntervalMap::new(&default);
+        test_map.insert(10, 20, &first_b);
+        test_map.insert(30, 40, &second_b);
+        assert!(!std::ptr::eq(test_map.get(&10), test_map.get(&30)));
+
+        test_map.intern_values();
+        assert!(std::ptr::eq(test_map.get(&10), test_map.get(&30)));
+        assert!(std::ptr::eq(test_map.get(&30), &first_b));
+        assert_eq!(test_map.get(&35), "b");
+    }
+
+    #[test]
+    fn nth_segment() {
+        let test_map: IntervalMap<i32, char> =
+            IntervalMap::from_events(vec![(i32::MIN, &'a'), (10, &'b'), (20, &'c')]);
+
+        assert_eq!(test_map.nth_segment(0), Some((i32::MIN, Some(10), &'a')));
+        assert_eq!(test_map.nth_segment(1), Some((10, Some(20), &'b')));
+        assert_eq!(test_map.nth_segment(2), Some((20, None, &'c')));
+        assert_eq!(test_map.nth_segment(3), None);
+    }
+
+    #[test]
+    fn offset_values() {
+        let mut owned: OwnedIntervalMap<i32, i32> = OwnedIntervalMap::new(0);
+        owned.insert(10, 20, 3);
+        owned.insert(20, 30, -2);
+
+        owned.offset_values(5);
+        assert_eq!(owned.get(&0), &5);
+        assert_eq!(owned.get(&15), &8);
+        assert_eq!(owned.get(&25), &3);
+        assert_eq!(owned.get(&30), &5);
+
+        // The default moved along, so removing resets to the offset baseline
+        owned.remove(10, 20);
+        assert_eq!(owned.get(&15), &5);
+    }
+
+    #[test]
+    #[should_panic(expected = "IntervalMap invariant: anchor at K::minimum must exist")]
+    fn get_without_anchor() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.raw_map_mut().remove(&i32::MIN);
+        test_map.get(&5);
+    }
+
+    #[test]
+    #[should_panic(expected = "IntervalMap invariant: anchor at K::minimum must exist")]
+    fn raw_entry_without_anchor() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.raw_map_mut().clear();
+        test_map.raw_entry(&5);
+    }
+
+    #[test]
+    fn get_sorted_slice() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(i32::MIN, -50, &'d');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+
+        let keys = [i32::MIN, -60, -50, 0, 10, 10, 14, 15, 24, 25, 100];
+        let expected: Vec<&char> = keys.iter().map(|key| test_map.get(key)).collect();
+        assert_eq!(test_map.get_sorted_slice(&keys), expected);
+        assert!(test_map.get_sorted_slice(&[]).is_empty());
+    }
+
+    #[test]
+    fn find_overlaps() {
+        let clean = [(10, 20, 'b'), (30, 40, 'c'), (20, 30, 'd'), (25, 25, 'e')];
+        assert!(super::find_overlaps(&clean).is_empty());
+
+        let overlapping = [(10, 20, 'b'), (30, 40, 'c'), (5, 12, 'd'), (0, 5, 'e')];
+        assert_eq!(super::find_overlaps(&overlapping), vec![(0, 2)]);
+
+        let nested = [(0, 100, 'b'), (10, 20, 'c'), (30, 40, 'd')];
+        assert_eq!(super::find_overlaps(&nested), vec![(0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn touching_ranges_coalesce() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        // [10, 20) and [20, 30) touch, as do [30, 40) and [40, 50)
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'b');
+        test_map.insert(30, 40, &'c');
+        test_map.insert(40, 50, &'d');
+
+        assert_eq!(
+            test_map.debug_dump(),
+            "[-inf,10)=a;[10,30)=b;[30,40)=c;[40,50)=d;[50,inf)=a"
+        );
+    }
+
+    #[test]
+    fn keep_dominant() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(0, 30, &'b');
+        test_map.insert(10, 12, &'c');
+        test_map.insert(20, 21, &'d');
+        test_map.insert(21, 30, &'e');
+
+        test_map.keep_dominant(10);
+        assert_eq!(
+            test_map.debug_dump(),
+            "[-inf,0)=a;[0,20)=b;[20,30)=e;[30,inf)=a"
+        );
+
+        // A clean boundary between two long segments does not move
+        let mut clean: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        clean.insert(0, 10, &'b');
+        clean.insert(10, 20, &'c');
+        clean.keep_dominant(4);
+        assert_eq!(
+            clean.debug_dump(),
+            "[-inf,0)=a;[0,10)=b;[10,20)=c;[20,inf)=a"
+        );
+    }
+
+    #[test]
+    fn segment_pairs_flagged() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'b');
+
+        assert_eq!(
+            test_map.segment_pairs_flagged().collect::<Vec<_>>(),
+            vec![
+                ((10, Some(20), &'b'), (20, Some(30), &'c'), true),
+                ((20, Some(30), &'c'), (40, Some(50), &'b'), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_linear() {
+        let mut test_map: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        test_map.insert(10, 20, &2);
+        test_map.insert(20, 30, &6);
+
+        assert_eq!(test_map.sample_linear(&0), 0.0);
+        assert_eq!(test_map.sample_linear(&9), 0.0);
+        assert_eq!(test_map.sample_linear(&10), 2.0);
+        assert_eq!(test_map.sample_linear(&15), 4.0);
+        assert_eq!(test_map.sample_linear(&25), 3.0);
+        assert_eq!(test_map.sample_linear(&30), 0.0);
+        assert_eq!(test_map.sample_linear(&100), 0.0);
+    }
+
+    #[test]
+    fn freeze() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(15, 25, &'c');
+        test_map.insert(i32::MIN, -10, &'d');
+
+        let frozen = test_map.freeze();
+        for key in [i32::MIN, -11, -10, 0, 10, 14, 15, 24, 25, i32::MAX] {
+            assert_eq!(frozen.get(&key), test_map.get(&key));
+        }
+    }
+
+    #[test]
+    fn point_count_for() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(30, 40, &'b');
+
+        assert_eq!(test_map.point_count_for(&'b', 15, 35), 5 + 5);
+        assert_eq!(test_map.point_count_for(&'c', 15, 35), 10);
+        assert_eq!(test_map.point_count_for(&'d', 15, 35), 0);
+    }
+
+    #[test]
+    fn set_window_events() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(0, 100, &'b');
+
+        test_map.set_window_events((20, 40), &[(25, &'c'), (30, &'d')]);
+        assert_eq!(
+            test_map.debug_dump(),
+            "[-inf,0)=a;[0,20)=b;[20,25)=a;[25,30)=c;[30,40)=d;[40,100)=b;[100,inf)=a"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "inside the window")]
+    fn set_window_events_outside() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.set_window_events((20, 40), &[(25, &'c'), (40, &'d')]);
+    }
+
+    #[test]
+    fn boundary_between() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'b');
+        test_map.insert(50, 60, &'c');
+
+        assert_eq!(test_map.boundary_between(&'b', &'c'), Some(20));
+        assert_eq!(test_map.boundary_between(&'c', &'a'), Some(30));
+        assert_eq!(test_map.boundary_between(&'c', &'b'), None);
+    }
+
+    #[test]
+    fn iter_clamped() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(30, 40, &'d');
+
+        assert_eq!(
+            test_map.iter_clamped(15, 35).collect::<Vec<_>>(),
+            vec![(15, 20, &'b'), (20, 30, &'c'), (30, 35, &'d')]
+        );
+        assert_eq!(
+            test_map.iter_clamped(20, 30).collect::<Vec<_>>(),
+            vec![(20, 30, &'c')]
+        );
+        assert_eq!(test_map.iter_clamped(30, 30).count(), 0);
+    }
+
+    #[test]
+    fn merge_all() {
+        static LEVELS: [i32; 8] = [0, 1, 2, 3, 4, 5, 6, 7];
+        let sum = |vals: &[&i32]| &LEVELS[vals.iter().copied().sum::<i32>() as usize];
+
+        let mut first: IntervalMap<i32, i32> = IntervalMap::new(&LEVELS[0]);
+        first.insert(10, 30, &LEVELS[1]);
+        let mut second: IntervalMap<i32, i32> = IntervalMap::new(&LEVELS[0]);
+        second.insert(20, 40, &LEVELS[2]);
+        let mut third: IntervalMap<i32, i32> = IntervalMap::new(&LEVELS[1]);
+        third.insert(25, 50, &LEVELS[3]);
+
+        let merged = IntervalMap::merge_all(&[&first, &second, &third], sum);
+        assert_eq!(
+            merged.debug_dump(),
+            "[-inf,10)=1;[10,20)=2;[20,25)=4;[25,30)=6;[30,40)=5;[40,50)=3;[50,inf)=1"
+        );
+
+        let pairwise = first
+            .merge(&second, |a, b| &LEVELS[(a + b) as usize])
+            .merge(&third, |a, b| &LEVELS[(a + b) as usize]);
+        assert_eq!(merged.m_map, pairwise.m_map);
+    }
+
+    #[test]
+    fn is_disjoint_from() {
+        let mut first: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        first.insert(10, 20, &'b');
+        first.insert(40, 50, &'c');
+        let mut second: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        second.insert(20, 40, &'b');
+
+        assert!(first.is_disjoint_from(&second));
+        assert!(second.is_disjoint_from(&first));
+
+        second.insert(45, 46, &'d');
+        assert!(!first.is_disjoint_from(&second));
+        assert!(!second.is_disjoint_from(&first));
+    }
+
+    #[test]
+    fn project() {
+        let mut status: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        status.insert(10, 30, &'b');
+        let mut cost: IntervalMap<i32, i32> = IntervalMap::new(&0);
+        cost.insert(20, 40, &5);
+
+        let projected = status.project(&cost);
+        assert_eq!(*projected.get(&5), (&'a', &0));
+        assert_eq!(*projected.get(&15), (&'b', &0));
+        assert_eq!(*projected.get(&25), (&'b', &5));
+        assert_eq!(*projected.get(&35), (&'a', &5));
+        assert_eq!(*projected.get(&45), (&'a', &0));
+    }
+
+    #[test]
+    fn drain_value() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.insert(20, 30, &'c');
+        test_map.insert(40, 50, &'b');
+
+        assert_eq!(
+            test_map.drain_value(&'b'),
+            vec![(10, Some(20)), (40, Some(50))]
+        );
+        assert_eq!(*test_map.get(&15), 'a');
+        assert_eq!(*test_map.get(&25), 'c');
+        assert_eq!(*test_map.get(&45), 'a');
+        assert_eq!(test_map.drain_value(&'b'), vec![]);
+        assert_eq!(test_map.drain_value(&'a'), vec![]);
+        assert_eq!(
+            test_map.to_vec(),
+            vec![(i32::MIN, &'a'), (20, &'c'), (30, &'a')]
+        );
+    }
+
+    #[test]
+    fn small_store_crossover() {
+        let vals: Vec<i32> = (0..4).collect();
+        let mut auto: IntervalMap<i32, i32> = IntervalMap::new(&vals[0]);
+        let mut small: IntervalMap<i32, i32> = IntervalMap::new_small(&vals[0]);
+        let mut large: IntervalMap<i32, i32> =
+            IntervalMap::with_default_and_capacity_hint(&vals[0], 1000);
+        assert!(auto.m_map.is_small());
+        assert!(!large.m_map.is_small());
+
+        for i in 0..SMALL_LIMIT as i32 {
+            for imap in [&mut auto, &mut small, &mut large] {
+                imap.insert(i * 10, i * 10 + 5, &vals[(i % 3 + 1) as usize]);
+            }
+            assert_eq!(auto.m_map, large.m_map);
+            assert_eq!(small.m_map, large.m_map);
+        }
+        assert!(!auto.m_map.is_small());
+        assert!(small.m_map.is_small());
+        assert!(small.m_map.len() > SMALL_LIMIT);
+
+        for imap in [&mut auto, &mut small, &mut large] {
+            imap.insert(12, 64, &vals[3]);
+            imap.remove(100, 120);
+        }
+        assert_eq!(auto.m_map, large.m_map);
+        assert_eq!(small.m_map, large.m_map);
+        for key in -5..200 {
+            assert_eq!(auto.get(&key), large.get(&key));
+            assert_eq!(small.get(&key), large.get(&key));
+        }
+
+        auto.clear();
+        assert!(auto.m_map.is_small());
+        assert_eq!(auto.to_vec(), vec![(i32::MIN, &vals[0])]);
+    }
+
+    #[test]
+    fn conflicts_ignore_rearranging() {
+        let mut test_map: IntervalMap<i32, char> = IntervalMap::new_logging(&'a');
+        test_map.insert(10, 20, &'b');
+        test_map.remove(10, 20);
+        test_map.insert(30, 40, &'c');
+        test_map.move_range((30, 40), 35);
+        test_map.swap_ranges((0, 10), (35, 45));
+        test_map.insert(50, 60, &'d');
+        assert!(test_map.remove_changed(50, 60));
+        test_map.insert_blend(0, 10, &'e', |_, _| &'e');
+        assert!(test_map.conflicts().is_empty());
+        assert_eq!(*test_map.get(&5), 'e');
+    }
+
     // fn canonical() {
 
     // }